@@ -0,0 +1,19 @@
+#!/bin/sh
+# Regenerates the screenshots of the README with the headless mode, from the root of the repository
+set -e
+
+shot() {
+    rom=$1
+    frame=$2
+    picture=$3
+    shift 3
+    cargo run -q --no-default-features -- headless "roms/$rom" --frames "$frame" --scale 10 \
+        --screenshot-at-frame "$frame" "pictures/$picture" "$@" > /dev/null
+}
+
+shot LUNAR_LANDER 130 lunar_lander.png --press 1@30 --release 1@35
+shot ASTRO_DODGE 400 astro_dodge.png
+shot BRIX 300 brix.png
+shot MAZE 120 maze.png
+shot PUZZLE 300 puzzle.png
+shot TETRIS 400 tetris.png
@@ -1,50 +1,341 @@
+use crate::chip8_error::Chip8Error;
+use crate::chip8_font::{
+    BigFont, Font, BIG_CHARACTER_SIZE, BIG_FONT_ADDRESS, BIG_FONT_SIZE, CHARACTER_SIZE,
+    DEFAULT_BIG_FONT, DEFAULT_FONT, FONT_ADDRESS, FONT_SIZE,
+};
+use crate::chip8_instruction::Instruction;
+use crate::chip8_quirks::{IndexIncrement, Quirks};
+use crate::chip8_rng::Rng;
+use crate::chip8_state::{StateReader, StateWriter};
 use crate::chip8_util::validate_argument;
 use num::CheckedAdd;
 use num::CheckedSub;
-use rand::Rng;
+
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+// SUPER-CHIP high resolution mode, switched on by 00FF
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+// Amount of RPL user flags FX75 and FX85 can save and restore
+pub const FLAG_COUNT: usize = 16;
+pub const MEMORY_SIZE: usize = 4096;
+// XO-CHIP machines address 64 KiB of memory
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
+// XO-CHIP draws on two bitplanes, a pixel holds one bit per plane
+pub const PLANE_COUNT: usize = 2;
+// Length in bytes of the XO-CHIP audio pattern, played one bit per sample
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+// Pitch register value at which the audio pattern plays at 4000 bits per second
+const DEFAULT_PITCH: u8 = 64;
+pub const PROGRAM_START: usize = 0x200;
+pub const STACK_SIZE: usize = 16;
+pub const KEY_COUNT: u8 = 16;
+// The delay and sound timers count down at 60 Hz, one frame is one tick of the timers.
+pub const FRAMES_PER_SECOND: u32 = 60;
+// About 600 instructions per second, which most games are tuned for.
+pub const DEFAULT_CYCLES_PER_FRAME: usize = 10;
+
+// Value of wait_key_state when no FX0A instruction is pending
+const NOT_WAITING_FOR_KEY: u8 = 0xF0;
+
+// What a call to fetch_cycle did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    // An instruction was executed.
+    Executed,
+    // An FX0A instruction is blocking execution until a key is pressed.
+    WaitingForKey,
+    // A sprite was drawn with the display wait quirk, execution resumes on the next frame.
+    WaitingForVBlank,
+    // The program ran the SUPER-CHIP 00FD exit instruction, nothing is executed anymore.
+    Exited,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+// A byte of memory read or written by an instruction through I, recorded while memory
+// tracing is on. Reads have the same old and new value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub addr: usize,
+    pub kind: AccessKind,
+    pub old_value: u8,
+    pub new_value: u8,
+}
 
 pub struct Chip8 {
-    memory: [u8; 4096],
-    pub cpu_registers: [u8; 16],
+    memory: Vec<u8>,
+    cpu_registers: [u8; 16],
     index_register: u16,
     program_counter: u16,
-    pub gfx: [u8; 64 * 32],
+    gfx: Vec<u8>,
+    is_high_resolution: bool,
     delay_timer: u8,
     sound_timer: u8,
+    // The sound timer was running when the timers last ticked
+    has_sounded: bool,
     stack_data: Vec<u16>,
-    pub key_states: u16,
-    pub wait_key_state: u8,
+    key_states: u16,
+    wait_key_state: u8,
+    quirks: Quirks,
+    rng: Rng,
+    is_waiting_for_vblank: bool,
+    rpl_flags: [u8; FLAG_COUNT],
+    has_exited: bool,
+    selected_planes: u8,
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    is_tracing_memory: bool,
+    memory_accesses: Vec<MemoryAccess>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::new(Quirks::default())
+    }
 }
 
 impl Chip8 {
-    pub fn new() -> Chip8 {
-        Chip8 {
-            memory: [0; 4096],
+    // Creates a machine with the standard hexadecimal font installed at FONT_ADDRESS.
+    // The quirks decide how the instructions that differ between interpreters behave.
+    pub fn new(quirks: Quirks) -> Chip8 {
+        Chip8::with_memory_size(quirks, MEMORY_SIZE)
+    }
+
+    // Creates an XO-CHIP machine, which has 64 KiB of memory instead of 4 KiB.
+    pub fn new_xo_chip(quirks: Quirks) -> Chip8 {
+        Chip8::with_memory_size(quirks, XO_CHIP_MEMORY_SIZE)
+    }
+
+    fn with_memory_size(quirks: Quirks, memory_size: usize) -> Chip8 {
+        let mut chip8 = Chip8 {
+            memory: vec![0; memory_size],
             cpu_registers: [0; 16],
             index_register: 0,
-            program_counter: 0x200, // CHIP8 expects PC to start at 0x200
-            gfx: [0; 64 * 32],
+            program_counter: PROGRAM_START as u16, // CHIP8 expects PC to start at 0x200
+            gfx: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            is_high_resolution: false,
             delay_timer: 0,
             sound_timer: 0,
-            stack_data: vec![0; 16],
+            stack_data: Vec::with_capacity(STACK_SIZE),
             key_states: 0,
-            wait_key_state: 0xF0,
+            wait_key_state: NOT_WAITING_FOR_KEY,
+            quirks,
+            rng: Rng::from_entropy(),
+            is_waiting_for_vblank: false,
+            has_sounded: false,
+            rpl_flags: [0; FLAG_COUNT],
+            has_exited: false,
+            selected_planes: 0b01,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            is_tracing_memory: false,
+            memory_accesses: Vec::new(),
+        };
+        chip8.set_font(&DEFAULT_FONT);
+        chip8.set_big_font(&DEFAULT_BIG_FONT);
+        chip8
+    }
+
+    // Replaces the hexadecimal digit sprites used by FX29.
+    pub fn set_font(&mut self, font: &Font) {
+        self.memory[FONT_ADDRESS..(FONT_ADDRESS + FONT_SIZE)].copy_from_slice(font);
+    }
+
+    // Replaces the large hexadecimal digit sprites used by FX30.
+    pub fn set_big_font(&mut self, font: &BigFont) {
+        self.memory[BIG_FONT_ADDRESS..(BIG_FONT_ADDRESS + BIG_FONT_SIZE)].copy_from_slice(font);
+    }
+
+    // Reseeds the random number generator of CXNN, so runs with the same seed and input
+    // are identical. A new machine is seeded with a different number every time.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    // Executes a single instruction.
+    // While an FX0A instruction is waiting for a key, no instruction is executed until
+    // a key is pressed.
+    // On error, the program counter points to the instruction after the faulty one.
+    pub fn fetch_cycle(&mut self) -> Result<StepOutcome, Chip8Error> {
+        self.memory_accesses.clear();
+        if self.has_exited {
+            return Ok(StepOutcome::Exited);
         }
+
+        if self.is_waiting_for_key() {
+            self.resolve_key_wait();
+            return Ok(StepOutcome::WaitingForKey);
+        }
+
+        if self.is_waiting_for_vblank {
+            return Ok(StepOutcome::WaitingForVBlank);
+        }
+
+        let opcode: u16 = self.fetch_next()?;
+        self.execute_instruction(opcode)?;
+        if self.has_exited {
+            return Ok(StepOutcome::Exited);
+        }
+        Ok(StepOutcome::Executed)
+    }
+
+    // Runs 1/60 s of emulation: executes the given amount of instructions,
+    // then ticks the timers once.
+    // The speed of the CPU is cycles_per_frame * FRAMES_PER_SECOND instructions per second,
+    // while the timers always tick at FRAMES_PER_SECOND.
+    // Stops at the first error without ticking the timers.
+    pub fn run_frame(&mut self, cycles_per_frame: usize) -> Result<(), Chip8Error> {
+        for _ in 0..cycles_per_frame {
+            self.fetch_cycle()?;
+        }
+        self.decrement_timers();
+        Ok(())
+    }
+
+    pub fn init_memory(
+        &mut self,
+        read_only_memory: &[u8],
+        start_index: usize,
+    ) -> Result<(), Chip8Error> {
+        let end_index: usize = start_index + read_only_memory.len();
+        if end_index > self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                addr: end_index - 1,
+            });
+        }
+        self.memory[start_index..end_index].copy_from_slice(read_only_memory);
+        Ok(())
+    }
+
+    // Loads a cartridge ROM at the address where programs start.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        self.init_memory(rom, PROGRAM_START)
     }
 
-    pub fn fetch_cycle(&mut self) {
-        let opcode: u16 = self.fetch_next();
-        self.execute_instruction(opcode);
+    // Snapshots the whole machine, see chip8_state for the format.
+    // The generator of CXNN is included, so a restored game draws the same numbers.
+    // The memory tracing of the debugger is not part of the state.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer: StateWriter = StateWriter::new();
+        writer.u32(self.memory.len() as u32);
+        writer.bytes(&self.memory);
+        writer.bytes(&self.cpu_registers);
+        writer.u16(self.index_register);
+        writer.u16(self.program_counter);
+        writer.bool(self.is_high_resolution);
+        writer.bytes(&self.gfx);
+        writer.u8(self.delay_timer);
+        writer.u8(self.sound_timer);
+        writer.u8(self.stack_data.len() as u8);
+        for address in &self.stack_data {
+            writer.u16(*address);
+        }
+        writer.u16(self.key_states);
+        writer.u8(self.wait_key_state);
+        self.quirks.write(&mut writer);
+        writer.bool(self.is_waiting_for_vblank);
+        writer.bytes(&self.rpl_flags);
+        writer.bool(self.has_exited);
+        writer.u8(self.selected_planes);
+        writer.bool(self.audio_pattern.is_some());
+        writer.bytes(&self.audio_pattern.unwrap_or([0; AUDIO_PATTERN_SIZE]));
+        writer.u8(self.pitch);
+        writer.u64(self.rng.state());
+        writer.finish()
     }
 
-    pub fn init_memory(&mut self, read_only_memory: &[u8], start_index: usize) {
-        let rom_length: usize = read_only_memory.len();
-        for i in start_index..(start_index + rom_length) {
-            self.memory[i as usize] = read_only_memory[(i - start_index) as usize];
+    // Restores a snapshot made by save_state, including its memory size and quirks.
+    // The machine is left untouched when the state is invalid.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Chip8Error> {
+        let invalid = |reason: &'static str| Chip8Error::InvalidSaveState { reason };
+        let mut reader: StateReader = StateReader::open(state)?;
+
+        let memory_size: usize = reader.u32()? as usize;
+        if memory_size != MEMORY_SIZE && memory_size != XO_CHIP_MEMORY_SIZE {
+            return Err(invalid("unknown memory size"));
         }
+        let memory: Vec<u8> = reader.bytes(memory_size)?.to_vec();
+        let mut cpu_registers: [u8; 16] = [0; 16];
+        cpu_registers.copy_from_slice(reader.bytes(16)?);
+        let index_register: u16 = reader.u16()?;
+        let program_counter: u16 = reader.u16()?;
+        let is_high_resolution: bool = reader.bool()?;
+        let gfx_size: usize = if is_high_resolution {
+            HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT
+        } else {
+            DISPLAY_WIDTH * DISPLAY_HEIGHT
+        };
+        let gfx: Vec<u8> = reader.bytes(gfx_size)?.to_vec();
+        let delay_timer: u8 = reader.u8()?;
+        let sound_timer: u8 = reader.u8()?;
+        let stack_size: usize = reader.u8()? as usize;
+        if stack_size > STACK_SIZE {
+            return Err(invalid("stack overflow"));
+        }
+        let mut stack_data: Vec<u16> = Vec::with_capacity(STACK_SIZE);
+        for _ in 0..stack_size {
+            stack_data.push(reader.u16()?);
+        }
+        let key_states: u16 = reader.u16()?;
+        let wait_key_state: u8 = reader.u8()?;
+        if wait_key_state != NOT_WAITING_FOR_KEY && wait_key_state > 0xF {
+            return Err(invalid("unknown key wait register"));
+        }
+        let quirks: Quirks = Quirks::read(&mut reader)?;
+        let is_waiting_for_vblank: bool = reader.bool()?;
+        let mut rpl_flags: [u8; FLAG_COUNT] = [0; FLAG_COUNT];
+        rpl_flags.copy_from_slice(reader.bytes(FLAG_COUNT)?);
+        let has_exited: bool = reader.bool()?;
+        let selected_planes: u8 = reader.u8()?;
+        if selected_planes > 0b11 {
+            return Err(invalid("unknown planes"));
+        }
+        let has_audio_pattern: bool = reader.bool()?;
+        let mut pattern: [u8; AUDIO_PATTERN_SIZE] = [0; AUDIO_PATTERN_SIZE];
+        pattern.copy_from_slice(reader.bytes(AUDIO_PATTERN_SIZE)?);
+        let pitch: u8 = reader.u8()?;
+        let rng: Rng = Rng::new(reader.u64()?);
+        reader.finish()?;
+
+        self.memory = memory;
+        self.cpu_registers = cpu_registers;
+        self.index_register = index_register;
+        self.program_counter = program_counter;
+        self.is_high_resolution = is_high_resolution;
+        self.gfx = gfx;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.stack_data = stack_data;
+        self.key_states = key_states;
+        self.wait_key_state = wait_key_state;
+        self.quirks = quirks;
+        self.is_waiting_for_vblank = is_waiting_for_vblank;
+        self.has_sounded = false;
+        self.rpl_flags = rpl_flags;
+        self.has_exited = has_exited;
+        self.selected_planes = selected_planes;
+        self.audio_pattern = if has_audio_pattern {
+            Some(pattern)
+        } else {
+            None
+        };
+        self.pitch = pitch;
+        self.rng = rng;
+        self.memory_accesses.clear();
+        Ok(())
     }
 
+    // Ticks the timers, must be called once per frame.
+    // This is also where the display wait quirk lets execution resume.
     pub fn decrement_timers(&mut self) {
+        self.is_waiting_for_vblank = false;
+        self.has_sounded = self.sound_timer > 0;
+
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -54,123 +345,281 @@ impl Chip8 {
         }
     }
 
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // Address of the next instruction to execute.
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    // V0 through VF.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.cpu_registers
+    }
+
+    // Return addresses of the subroutines being called, the innermost call last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack_data
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // Records the memory accesses of each instruction, see memory_accesses.
+    pub fn set_memory_tracing(&mut self, is_tracing_memory: bool) {
+        self.is_tracing_memory = is_tracing_memory;
+        self.memory_accesses.clear();
+    }
+
+    // The reads and writes done by the last call to fetch_cycle, in order, when memory
+    // tracing is on. Instruction fetches are not included.
+    pub fn memory_accesses(&self) -> &[MemoryAccess] {
+        &self.memory_accesses
+    }
+
     pub fn is_sound_active(&self) -> bool {
         self.sound_timer > 0
     }
 
+    // True when the buzzer sounded during the frame that just ran, which is what frontends
+    // should play. Setting the sound timer to N sounds it for exactly N frames, even N = 1,
+    // while is_sound_active is already false at the end of the last one.
+    pub fn has_sounded(&self) -> bool {
+        self.has_sounded
+    }
+
+    // The display, one byte per pixel, row by row.
+    // Each pixel holds the planes it is lit on, bit 0 for the first plane and bit 1 for the
+    // second one. Only XO-CHIP programs draw on the second plane, so a pixel is otherwise
+    // 0 when off and 1 when on.
+    // Its size depends on the resolution, see display_width and display_height.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.gfx
+    }
+
+    pub fn display_width(&self) -> usize {
+        if self.is_high_resolution {
+            HIRES_DISPLAY_WIDTH
+        } else {
+            DISPLAY_WIDTH
+        }
+    }
+
+    pub fn display_height(&self) -> usize {
+        if self.is_high_resolution {
+            HIRES_DISPLAY_HEIGHT
+        } else {
+            DISPLAY_HEIGHT
+        }
+    }
+
+    pub fn is_high_resolution(&self) -> bool {
+        self.is_high_resolution
+    }
+
+    // True for machines created with new_xo_chip.
+    pub fn is_xo_chip(&self) -> bool {
+        self.memory.len() == XO_CHIP_MEMORY_SIZE
+    }
+
+    // The XO-CHIP audio pattern loaded by F002, or None when the program never loaded one
+    // and the buzzer plays its usual tone.
+    pub fn audio_pattern(&self) -> Option<&[u8; AUDIO_PATTERN_SIZE]> {
+        self.audio_pattern.as_ref()
+    }
+
+    // How many bits of the audio pattern are played per second, as set by FX3A.
+    pub fn audio_pattern_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+    }
+
+    // True once the program ran the SUPER-CHIP exit instruction.
+    pub fn has_exited(&self) -> bool {
+        self.has_exited
+    }
+
+    // Marks the hex keypad key (0x0 to 0xF) as held down.
+    pub fn press_key(&mut self, key: u8) -> Result<(), Chip8Error> {
+        validate_argument(key, 0xF)?;
+        self.key_states |= 0x8000 >> key;
+        Ok(())
+    }
+
+    // Marks the hex keypad key (0x0 to 0xF) as released.
+    pub fn release_key(&mut self, key: u8) -> Result<(), Chip8Error> {
+        validate_argument(key, 0xF)?;
+        self.key_states &= !(0x8000 >> key);
+        Ok(())
+    }
+
+    // Only the lowest four bits of key are used.
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        (self.key_states >> (15 - (key & 0xF))) & 0x1 == 1
+    }
+
+    // True while an FX0A instruction is blocking execution.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.wait_key_state & 0xF0 != 0xF0
+    }
+
+    // Stores the lowest pressed key in the register FX0A is waiting on, if any key is pressed.
+    fn resolve_key_wait(&mut self) {
+        if let Some(key) = (0..KEY_COUNT).find(|key| self.is_key_pressed(*key)) {
+            self.cpu_registers[self.wait_key_state as usize] = key;
+            self.wait_key_state = NOT_WAITING_FOR_KEY;
+        }
+    }
+
+    // Checks that addr is inside of memory
+    fn check_address(&self, addr: usize) -> Result<usize, Chip8Error> {
+        if addr >= self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds { addr });
+        }
+        Ok(addr)
+    }
+
+    // Reads a byte of data, as opposed to an instruction
+    fn read_byte(&mut self, addr: usize) -> Result<u8, Chip8Error> {
+        let value: u8 = self.memory[self.check_address(addr)?];
+        if self.is_tracing_memory {
+            self.memory_accesses.push(MemoryAccess {
+                addr,
+                kind: AccessKind::Read,
+                old_value: value,
+                new_value: value,
+            });
+        }
+        Ok(value)
+    }
+
+    fn write_byte(&mut self, addr: usize, value: u8) -> Result<(), Chip8Error> {
+        let old_value: u8 = self.memory[self.check_address(addr)?];
+        if self.is_tracing_memory {
+            self.memory_accesses.push(MemoryAccess {
+                addr,
+                kind: AccessKind::Write,
+                old_value,
+                new_value: value,
+            });
+        }
+        self.memory[addr] = value;
+        Ok(())
+    }
+
+    // The error for an opcode that was just fetched but cannot be decoded
+    fn unknown_opcode(&self, opcode: u16) -> Chip8Error {
+        Chip8Error::UnknownOpcode {
+            pc: self.program_counter.wrapping_sub(2),
+            opcode,
+        }
+    }
+
     // Executes the given opcode
     // Includes decoding and executing the given opcode
-    fn execute_instruction(&mut self, opcode: u16) {
-        match opcode & 0xF000 {
-            0x0000 => match opcode {
-                0x000 => (), // Used for old machines, do nothing here.
-                0x0E0 => self.clear_screen(),
-                0x0EE => self.subroutine_return(),
-                _ => panic!("Unknown opcode: {}", opcode),
-            },
-            0x1000 => self.jump_to_address(opcode & 0x0FFF),
-            0x2000 => self.call_address(opcode & 0x0FFF),
-            0x3000 => self.skip_next_if_byte_is_vx(
-                ((opcode & 0x0F00) >> 8) as u8, // XKK
-                (opcode & 0x0FF) as u8,
-            ),
-            0x4000 => self.skip_next_if_byte_is_not_vx(
-                ((opcode & 0x0F00) >> 8) as u8, // XKK
-                (opcode & 0x0FF) as u8,
-            ),
-            0x5000 => self.skip_next_if_vx_eql_vy(
-                ((opcode & 0xF00) >> 8) as u8, // XY0
-                ((opcode & 0x0F0) >> 4) as u8,
-            ),
-            0x6000 => self.set_register_value(
-                // XKK
-                ((opcode & 0xF00) >> 8) as u8,
-                (opcode & 0x0FF) as u8,
-            ),
-            0x7000 => self.add(
-                // XKK
-                ((opcode & 0xF00) >> 8) as u8,
-                (opcode & 0x0FF) as u8,
-            ),
-            0x8000 => match opcode & 0xF00F {
-                0x8000 => self.load(
-                    ((opcode & 0x0F00) >> 8) as u8,
-                    ((opcode & 0x00F0) >> 4) as u8,
-                ),
-                0x8001 => self.bit_or(
-                    ((opcode & 0x0F00) >> 8) as u8,
-                    ((opcode & 0x00F0) >> 4) as u8,
-                ),
-                0x8002 => self.bit_and(
-                    ((opcode & 0x0F00) >> 8) as u8,
-                    ((opcode & 0x00F0) >> 4) as u8,
-                ),
-                0x8003 => self.bit_xor(
-                    ((opcode & 0x0F00) >> 8) as u8,
-                    ((opcode & 0x00F0) >> 4) as u8,
-                ),
-                0x8004 => self.add_registers(
-                    ((opcode & 0x0F00) >> 8) as u8,
-                    ((opcode & 0x00F0) >> 4) as u8,
-                ),
-                0x8005 => self.sub_registers(
-                    ((opcode & 0x0F00) >> 8) as u8,
-                    ((opcode & 0x00F0) >> 4) as u8,
-                ),
-                0x8006 => self.shift_right_register(((opcode & 0x0F00) >> 8) as u8),
-                0x8007 => self.sub_registers_not(
-                    ((opcode & 0x0F00) >> 8) as u8,
-                    ((opcode & 0x00F0) >> 4) as u8,
-                ),
-                0x800E => self.shift_left_register(
-                    ((opcode & 0x0F00) >> 8) as u8,
-                    ((opcode & 0x00F0) >> 4) as u8,
-                ),
-                _ => panic!("Unknown opcode: {}", opcode),
-            },
-            0x9000 => self.skip_next_if_vx_not_eql_vy(
-                ((opcode & 0x0F00) >> 8) as u8,
-                ((opcode & 0x00F0) >> 4) as u8,
-            ),
-            0xA000 => self.set_index_register(opcode & 0x0FFF),
-            0xB000 => self.jump_to_address_plus_v0(opcode & 0x0FFF),
-            0xC000 => self.set_rand(((opcode & 0x0F00) >> 8) as u8, (opcode & 0x0FF) as u8),
-            0xD000 => self.draw(
-                ((opcode & 0x0F00) >> 8) as u8,
-                ((opcode & 0x00F0) >> 4) as u8,
-                (opcode & 0xF) as u8,
-            ),
-            0xE000 => match opcode & 0xF0FF {
-                0xE09E => self.skip_if_key_down(((opcode & 0x0F00) >> 8) as u8),
-                0xE0A1 => self.skip_if_key_up(((opcode & 0x0F00) >> 8) as u8),
-                _ => panic!("Unknown opcode: {}", opcode),
-            },
-            0xF000 => match opcode & 0xF0FF {
-                0xF007 => self.read_delay_timer(((opcode & 0x0F00) >> 8) as u8),
-                0xF00A => self.wait_for_key(((opcode & 0x0F00) >> 8) as u8),
-                0xF015 => self.set_delay_timer(((opcode & 0x0F00) >> 8) as u8),
-                0xF018 => self.set_sound_timer(((opcode & 0x0F00) >> 8) as u8),
-                0xF01E => self.index_reg_add(((opcode & 0x0F00) >> 8) as u8),
-                0xF029 => self.set_index_to_character_address(((opcode & 0x0F00) >> 8) as u8),
-                0xF033 => self.store_bcd(((opcode & 0x0F00) >> 8) as u8),
-                0xF055 => self.store_registers(((opcode & 0x0F00) >> 8) as u8),
-                0xF065 => self.read_memory(((opcode & 0x0F00) >> 8) as u8),
-                _ => panic!("Unknown opcode: {}", opcode),
-            },
-            _ => {
-                panic!("Unknown opcode: {}", opcode);
+    fn execute_instruction(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let instruction: Instruction = match Instruction::decode(opcode) {
+            Some(instruction) if instruction.is_xo_chip() && !self.is_xo_chip() => {
+                return Err(self.unknown_opcode(opcode))
+            }
+            Some(instruction) => instruction,
+            None => return Err(self.unknown_opcode(opcode)),
+        };
+
+        match instruction {
+            Instruction::Nop => Ok(()),
+            Instruction::ClearScreen => self.clear_screen(),
+            Instruction::Return => self.subroutine_return(),
+            Instruction::ScrollDown { n } => self.scroll_down(n),
+            Instruction::ScrollUp { n } => self.scroll_up(n),
+            Instruction::ScrollRight => self.scroll_right(),
+            Instruction::ScrollLeft => self.scroll_left(),
+            Instruction::Exit => self.exit(),
+            Instruction::LowResolution => self.set_high_resolution(false),
+            Instruction::HighResolution => self.set_high_resolution(true),
+            Instruction::Jump { address } => self.jump_to_address(address),
+            Instruction::Call { address } => self.call_address(address),
+            Instruction::SkipIfEqualByte { x, byte } => self.skip_next_if_byte_is_vx(x, byte),
+            Instruction::SkipIfNotEqualByte { x, byte } => {
+                self.skip_next_if_byte_is_not_vx(x, byte)
             }
+            Instruction::SkipIfEqual { x, y } => self.skip_next_if_vx_eql_vy(x, y),
+            Instruction::StoreRegisterRange { x, y } => self.store_register_range(x, y),
+            Instruction::ReadRegisterRange { x, y } => self.read_register_range(x, y),
+            Instruction::LoadByte { x, byte } => self.set_register_value(x, byte),
+            Instruction::AddByte { x, byte } => self.add(x, byte),
+            Instruction::Load { x, y } => self.load(x, y),
+            Instruction::Or { x, y } => self.bit_or(x, y),
+            Instruction::And { x, y } => self.bit_and(x, y),
+            Instruction::Xor { x, y } => self.bit_xor(x, y),
+            Instruction::Add { x, y } => self.add_registers(x, y),
+            Instruction::Sub { x, y } => self.sub_registers(x, y),
+            Instruction::ShiftRight { x, y } => self.shift_right_register(x, y),
+            Instruction::SubNot { x, y } => self.sub_registers_not(x, y),
+            Instruction::ShiftLeft { x, y } => self.shift_left_register(x, y),
+            Instruction::SkipIfNotEqual { x, y } => self.skip_next_if_vx_not_eql_vy(x, y),
+            Instruction::LoadIndex { address } => self.set_index_register(address),
+            Instruction::JumpPlusV0 { address } => self.jump_to_address_plus_v0(address),
+            Instruction::Random { x, byte } => self.set_rand(x, byte),
+            Instruction::Draw { x, y, n } => self.draw(x, y, n),
+            Instruction::SkipIfKeyDown { x } => self.skip_if_key_down(x),
+            Instruction::SkipIfKeyUp { x } => self.skip_if_key_up(x),
+            Instruction::LoadIndexLong => self.set_index_register_long(),
+            Instruction::SelectPlanes { n } => self.select_planes(n),
+            Instruction::LoadAudioPattern => self.load_audio_pattern(),
+            Instruction::ReadDelayTimer { x } => self.read_delay_timer(x),
+            Instruction::WaitForKey { x } => self.wait_for_key(x),
+            Instruction::SetDelayTimer { x } => self.set_delay_timer(x),
+            Instruction::SetSoundTimer { x } => self.set_sound_timer(x),
+            Instruction::AddIndex { x } => self.index_reg_add(x),
+            Instruction::LoadCharacter { x } => self.set_index_to_character_address(x),
+            Instruction::LoadBigCharacter { x } => self.set_index_to_big_character_address(x),
+            Instruction::StoreBcd { x } => self.store_bcd(x),
+            Instruction::SetPitch { x } => self.set_pitch(x),
+            Instruction::StoreRegisters { x } => self.store_registers(x),
+            Instruction::ReadRegisters { x } => self.read_memory(x),
+            Instruction::StoreFlags { x } => self.store_flags(x),
+            Instruction::ReadFlags { x } => self.read_flags(x),
         }
     }
 
     // essentially combine PC: u8 and PC+1: u8 into one u16 opcode to execute using bitshift ops
-    fn fetch_next(&mut self) -> u16 {
-        let opcode: u16 = (self.memory[self.program_counter as usize] as u16) << 8
-            | self.memory[(self.program_counter + 1) as usize] as u16;
+    // PC wraps around at the end of the XO-CHIP 64 KiB memory, like I does.
+    fn fetch_next(&mut self) -> Result<u16, Chip8Error> {
+        let address: usize = self.program_counter as usize;
+        let opcode: u16 = (self.memory[self.check_address(address)?] as u16) << 8
+            | self.memory[self.check_address(address + 1)?] as u16;
+
+        self.program_counter = self.program_counter.wrapping_add(2);
 
-        self.program_counter += 2;
+        Ok(opcode)
+    }
 
-        opcode
+    // Skips the next instruction, which is 4 bytes long when it is the XO-CHIP F000 NNNN.
+    // Other machines have no such instruction, F0 00 is just data to them.
+    fn skip_next_instruction(&mut self) {
+        let address: usize = self.program_counter as usize;
+        let is_long_instruction: bool = self.is_xo_chip()
+            && address + 1 < self.memory.len()
+            && self.memory[address] == 0xF0
+            && self.memory[address + 1] == 0x00;
+        self.program_counter =
+            self.program_counter
+                .wrapping_add(if is_long_instruction { 4 } else { 2 });
     }
 
     //
@@ -180,133 +629,160 @@ impl Chip8 {
 
     // 00E0
     // Clears the screen.
-    fn clear_screen(&mut self) {
-        self.gfx = [0; 64 * 32];
+    // Only the selected XO-CHIP planes are cleared.
+    fn clear_screen(&mut self) -> Result<(), Chip8Error> {
+        for pixel in self.gfx.iter_mut() {
+            *pixel &= !self.selected_planes;
+        }
+        Ok(())
     }
 
     // 00EE
     // Return from a subroutine
     // The interpreter sets the program counter to the address at the top of the stack,
     // then subtracts 1 from the stack pointer.
-    fn subroutine_return(&mut self) {
-        match self.stack_data.last() {
+    fn subroutine_return(&mut self) -> Result<(), Chip8Error> {
+        match self.stack_data.pop() {
             Some(x) => {
-                self.program_counter = *x;
-                self.stack_data.pop();
+                self.program_counter = x;
+                Ok(())
             }
-            None => panic!("Nothing in the stack!"),
+            None => Err(Chip8Error::StackUnderflow),
         }
     }
 
     // 1NNN
     // Jump Address
     // The interpreter sets the program counter to nnn
-    fn jump_to_address(&mut self, address: u16) {
-        self.program_counter = validate_argument(address, 0x0FFF);
+    fn jump_to_address(&mut self, address: u16) -> Result<(), Chip8Error> {
+        self.program_counter = validate_argument(address, 0x0FFF)?;
+        Ok(())
     }
 
     // 2NNN
     // Call subroutine at nnn.
     // The interpreter increments the stack pointer,
     // then puts the current PC on the top of the stack. The PC is then set to nnn.
-    fn call_address(&mut self, address: u16) {
-        self.stack_data.push((self.program_counter) as u16);
-        self.program_counter = validate_argument(address, 0x0FFF);
+    fn call_address(&mut self, address: u16) -> Result<(), Chip8Error> {
+        if self.stack_data.len() >= STACK_SIZE {
+            return Err(Chip8Error::StackOverflow);
+        }
+        self.stack_data.push(self.program_counter);
+        self.program_counter = validate_argument(address, 0x0FFF)?;
+        Ok(())
     }
 
     // 3XKK
     // Skip next instruction if VX = KK.
     // The interpreter compares register Vx to kk, and if they are equal,
     // increments the program counter by 2.
-    fn skip_next_if_byte_is_vx(&mut self, reg_x: u8, byte_value: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(byte_value, 0xFF);
+    fn skip_next_if_byte_is_vx(&mut self, reg_x: u8, byte_value: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(byte_value, 0xFF)?;
         if self.cpu_registers[reg_x as usize] == byte_value {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
+        Ok(())
     }
 
     // 4XKK
     // Skip next instruction if Vx != kk.
     // The interpreter compares register Vx to kk,
     // and if they are not equal, increments the program counter by 2.
-    fn skip_next_if_byte_is_not_vx(&mut self, reg_x: u8, byte_value: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(byte_value, 0xFF);
+    fn skip_next_if_byte_is_not_vx(&mut self, reg_x: u8, byte_value: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(byte_value, 0xFF)?;
         if self.cpu_registers[reg_x as usize] != byte_value {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
+        Ok(())
     }
 
     // 5XY0
     // Skip next instruction if Vx = Vy.
     // The interpreter compares register Vx to register Vy, and if they are equal,
     // increments the program counter by 2.
-    fn skip_next_if_vx_eql_vy(&mut self, reg_x: u8, reg_y: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(reg_y, 0xF);
+    fn skip_next_if_vx_eql_vy(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
         if self.cpu_registers[reg_x as usize] == self.cpu_registers[reg_y as usize] {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
+        Ok(())
     }
 
     // 6XKK
     // Set VX = KK.
     // The interpreter puts the value KK into register VX.
-    fn set_register_value(&mut self, reg_x: u8, byte_value: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(byte_value, 0xFF);
+    fn set_register_value(&mut self, reg_x: u8, byte_value: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(byte_value, 0xFF)?;
         self.cpu_registers[reg_x as usize] = byte_value;
+        Ok(())
     }
 
     // 7XKK
     // Set Vx = Vx + kk.
     // Adds the value kk to the value of register Vx, then stores the result in Vx.
-    fn add(&mut self, reg_x: u8, byte_value: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(byte_value, 0xFF);
+    fn add(&mut self, reg_x: u8, byte_value: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(byte_value, 0xFF)?;
 
         let mut result: u16 = byte_value as u16 + self.cpu_registers[reg_x as usize] as u16;
         if result > 255 {
             result -= 256;
         }
         self.cpu_registers[reg_x as usize] = result as u8;
+        Ok(())
     }
 
     // 8XY0
     // Set Vx = Vy.
     // Stores the value of register Vy in register Vx.
-    fn load(&mut self, reg_x: u8, reg_y: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(reg_y, 0xF);
+    fn load(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
         self.cpu_registers[reg_x as usize] = self.cpu_registers[reg_y as usize];
+        Ok(())
     }
 
     // 8XY1
     // Set Vx = Vx OR Vy.
     // Performs a bitwise OR on the values of Vx and Vy, then stores the result in Vx.
-    fn bit_or(&mut self, reg_x: u8, reg_y: u8) {
-        validate_argument(reg_x, 0xF) as usize;
-        validate_argument(reg_y, 0xF) as usize;
+    fn bit_or(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
         self.cpu_registers[reg_x as usize] |= self.cpu_registers[reg_y as usize];
+        if self.quirks.vf_reset {
+            self.cpu_registers[0xF] = 0;
+        }
+        Ok(())
     }
 
     // 8XY2
     // Set Vx = Vx AND Vy.
     // Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx.
-    fn bit_and(&mut self, reg_x: u8, reg_y: u8) {
-        validate_argument(reg_x, 0xF) as usize;
-        validate_argument(reg_y, 0xF) as usize;
+    fn bit_and(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
         self.cpu_registers[reg_x as usize] &= self.cpu_registers[reg_y as usize];
+        if self.quirks.vf_reset {
+            self.cpu_registers[0xF] = 0;
+        }
+        Ok(())
     }
 
     // 8XY3
     // Set Vx = Vx XOR Vy.
     // Performs a bitwise exclusive OR on the values of Vx and Vy, then stores the result in Vx.
-    fn bit_xor(&mut self, reg_x: u8, reg_y: u8) {
-        validate_argument(reg_x, 0xF) as usize;
-        validate_argument(reg_y, 0xF) as usize;
+    fn bit_xor(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
         self.cpu_registers[reg_x as usize] ^= self.cpu_registers[reg_y as usize];
+        if self.quirks.vf_reset {
+            self.cpu_registers[0xF] = 0;
+        }
+        Ok(())
     }
 
     // 8XY4
@@ -314,9 +790,9 @@ impl Chip8 {
     // The values of Vx and Vy are added together.
     // If the result is greater than 8 bits (i.e., > 255,) VF is set to 1, otherwise 0.
     // Only the lowest 8 bits of the result are kept, and stored in Vx.
-    fn add_registers(&mut self, reg_x: u8, reg_y: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(reg_y, 0xF);
+    fn add_registers(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
 
         let reg_x_val: u8 = self.cpu_registers[reg_x as usize];
         let reg_y_val: u8 = self.cpu_registers[reg_y as usize];
@@ -333,13 +809,14 @@ impl Chip8 {
                 self.cpu_registers[reg_x as usize] = (reg_x_val as u16 + reg_y_val as u16) as u8;
             }
         }
+        Ok(())
     }
 
     // 8XY5
     // VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-    fn sub_registers(&mut self, reg_x: u8, reg_y: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(reg_y, 0xF);
+    fn sub_registers(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
 
         let reg_x_val: u8 = self.cpu_registers[reg_x as usize];
         let reg_y_val: u8 = self.cpu_registers[reg_y as usize];
@@ -356,31 +833,34 @@ impl Chip8 {
                 self.cpu_registers[reg_x as usize] = 255 - ((reg_y_val - reg_x_val) - 1)
             }
         }
+        Ok(())
     }
 
     // 8XY6
     // Set Vx = Vx SHR 1.
     // If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0.
     // Then Vx is divided by 2.
-    fn shift_right_register(&mut self, reg_x: u8) {
-        validate_argument(reg_x, 0xF);
-        self.cpu_registers[0xF] = {
-            if self.cpu_registers[reg_x as usize] & 1 == 1 {
-                1
-            } else {
-                0
-            }
+    // With the shift quirk, Vy is shifted instead and the result is stored in Vx.
+    fn shift_right_register(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
+        let source: u8 = if self.quirks.shift_uses_vy {
+            self.cpu_registers[reg_y as usize]
+        } else {
+            self.cpu_registers[reg_x as usize]
         };
-        self.cpu_registers[reg_x as usize] >>= 1;
+        self.cpu_registers[reg_x as usize] = source >> 1;
+        self.cpu_registers[0xF] = source & 1;
+        Ok(())
     }
 
     // 8XY7
     // Set Vx = Vy - Vx, set VF = NOT borrow.
     // If Vy > Vx, then VF is set to 1, otherwise 0.
     // Then Vx is subtracted from Vy, and the results stored in Vx.
-    fn sub_registers_not(&mut self, reg_x: u8, reg_y: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(reg_y, 0xF);
+    fn sub_registers_not(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
 
         let reg_x_val: u8 = self.cpu_registers[reg_x as usize];
         let reg_y_val: u8 = self.cpu_registers[reg_y as usize];
@@ -397,48 +877,59 @@ impl Chip8 {
                 self.cpu_registers[reg_y as usize] = 255 - ((reg_x_val - reg_y_val) - 1)
             }
         }
+        Ok(())
     }
 
     // 8XYE
     // Set Vx = Vx SHL 1.
     // If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. Then Vx is multiplied by 2.
-    fn shift_left_register(&mut self, reg_x: u8, reg_y: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(reg_y, 0xF);
-        self.cpu_registers[0xF] = {
-            if (self.cpu_registers[reg_x as usize] >> 7) & 1 == 1 {
-                1
-            } else {
-                0
-            }
+    // With the shift quirk, Vy is shifted instead and the result is stored in Vx.
+    fn shift_left_register(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
+        let source: u8 = if self.quirks.shift_uses_vy {
+            self.cpu_registers[reg_y as usize]
+        } else {
+            self.cpu_registers[reg_x as usize]
         };
-        self.cpu_registers[reg_x as usize] = self.cpu_registers[reg_y as usize] << 1;
+        self.cpu_registers[reg_x as usize] = source << 1;
+        self.cpu_registers[0xF] = (source >> 7) & 1;
+        Ok(())
     }
 
     // 9XY0
     // Skip next instruction if Vx != Vy.
     // The values of Vx and Vy are compared, and if they are not equal,
     // the program counter is increased by 2.
-    fn skip_next_if_vx_not_eql_vy(&mut self, reg_x: u8, reg_y: u8) {
-        validate_argument(reg_x, 0xF);
-        validate_argument(reg_y, 0xF);
+    fn skip_next_if_vx_not_eql_vy(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
         if self.cpu_registers[reg_x as usize] != self.cpu_registers[reg_y as usize] {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
+        Ok(())
     }
 
     // ANNN
     // Sets I to the address NNN.
-    fn set_index_register(&mut self, value: u16) {
-        validate_argument(value, 0x0FFF);
+    fn set_index_register(&mut self, value: u16) -> Result<(), Chip8Error> {
+        validate_argument(value, 0x0FFF)?;
         self.index_register = value;
+        Ok(())
     }
 
     // BNNN
     // Jumps to the address NNN plus V0..
-    fn jump_to_address_plus_v0(&mut self, value: u16) {
-        validate_argument(value, 0xFFF);
-        self.program_counter = value + (self.cpu_registers[0] as u16);
+    // With the jump quirk, the instruction is read as BXNN and jumps to XNN plus VX.
+    fn jump_to_address_plus_v0(&mut self, value: u16) -> Result<(), Chip8Error> {
+        validate_argument(value, 0xFFF)?;
+        let reg: usize = if self.quirks.jump_uses_vx {
+            (value >> 8) as usize
+        } else {
+            0
+        };
+        self.program_counter = value + (self.cpu_registers[reg] as u16);
+        Ok(())
     }
 
     // CXNN
@@ -446,10 +937,10 @@ impl Chip8 {
     // The interpreter generates a random number from 0 to 255,
     // which is then ANDed with the value kk.
     // The results are stored in Vx.
-    fn set_rand(&mut self, reg_x: u8, value: u8) {
-        let mut rng = rand::thread_rng();
-        let random_num: u8 = rng.gen();
+    fn set_rand(&mut self, reg_x: u8, value: u8) -> Result<(), Chip8Error> {
+        let random_num: u8 = self.rng.next_u8();
         self.cpu_registers[reg_x as usize] = value & random_num;
+        Ok(())
     }
 
     // DXYN
@@ -460,45 +951,98 @@ impl Chip8 {
     // If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
     // If the sprite is positioned so part of it is outside the coordinates of the display,
     // it wraps around to the opposite side of the screen.
-    fn draw(&mut self, reg_x: u8, reg_y: u8, bytes_to_read: u8) {
-        validate_argument(reg_x, 0xFF);
-        validate_argument(reg_y, 0xFF);
+    // With the clipping quirk, the part outside of the display is not drawn instead.
+    // DXY0 (SUPER-CHIP) draws a 16x16 sprite made of 32 bytes, two bytes per row.
+    // With both XO-CHIP planes selected, the sprite for the second plane follows the one for
+    // the first plane in memory.
+    fn draw(&mut self, reg_x: u8, reg_y: u8, bytes_to_read: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xFF)?;
+        validate_argument(reg_y, 0xFF)?;
+
+        let (sprite_width, sprite_height): (usize, usize) = if bytes_to_read == 0 {
+            (16, 16)
+        } else {
+            (8, bytes_to_read as usize)
+        };
+        let bytes_per_row: usize = sprite_width / 8;
+
+        let display_width: usize = self.display_width();
+        let display_height: usize = self.display_height();
 
-        let x: u8 = self.cpu_registers[reg_x as usize];
-        let y: u8 = self.cpu_registers[reg_y as usize];
+        // The starting position always wraps around
+        let x: usize = self.cpu_registers[reg_x as usize] as usize % display_width;
+        let y: usize = self.cpu_registers[reg_y as usize] as usize % display_height;
 
-        let reading_address: u16 = self.index_register;
+        let mut reading_address: usize = self.index_register as usize;
 
         let mut pixel_was_erased: bool = false;
-        for i in 0..bytes_to_read {
-            let mut y_wrapped: u16 = y as u16 + i as u16;
-            y_wrapped %= 32;
-
-            if self.draw_byte(
-                x,
-                y_wrapped as u8,
-                self.memory[(reading_address + i as u16) as usize],
-            ) {
-                pixel_was_erased = true;
+        for plane in 0..PLANE_COUNT {
+            let plane_bit: u8 = 1 << plane;
+            if self.selected_planes & plane_bit == 0 {
+                continue;
+            }
+
+            for i in 0..sprite_height {
+                let mut row: usize = y + i;
+                if row >= display_height {
+                    if self.quirks.clip_sprites {
+                        break;
+                    }
+                    row %= display_height;
+                }
+
+                let mut sprite_row: u16 = 0;
+                for byte_index in 0..bytes_per_row {
+                    let sprite_address: usize = reading_address + i * bytes_per_row + byte_index;
+                    sprite_row = sprite_row << 8 | self.read_byte(sprite_address)? as u16;
+                }
+
+                if self.draw_row(x, row, sprite_row, sprite_width, plane_bit) {
+                    pixel_was_erased = true;
+                }
             }
+
+            reading_address += sprite_height * bytes_per_row;
         }
 
         self.cpu_registers[0xF] = if pixel_was_erased { 1 } else { 0 };
+
+        if self.quirks.display_wait {
+            self.is_waiting_for_vblank = true;
+        }
+        Ok(())
     }
 
-    // Draws byte
-    // Wraps around if needed
+    // Draws one row of a sprite on the plane, the leftmost pixel is the highest of the
+    // sprite_width bits
+    // Wraps around or clips if needed
     // Returns true if it cleared a pixel
-    fn draw_byte(&mut self, x: u8, y: u8, byte: u8) -> bool {
+    fn draw_row(
+        &mut self,
+        x: usize,
+        y: usize,
+        sprite_row: u16,
+        sprite_width: usize,
+        plane_bit: u8,
+    ) -> bool {
+        let display_width: usize = self.display_width();
         let mut pixel_was_erased = false;
-        let index: usize = ((y as usize) * 64 + (x as usize)) as usize;
-        for i in 0..8 {
-            let next_index: usize = ((index as u16 + i as u16) % 2048) as usize;
-            let pixel: u8 = self.gfx[next_index];
-            self.gfx[next_index] ^= if ((byte >> (7 - i)) & 1) == 1 { 255 } else { 0 };
+        for i in 0..sprite_width {
+            let mut column: usize = x + i;
+            if column >= display_width {
+                if self.quirks.clip_sprites {
+                    break;
+                }
+                column %= display_width;
+            }
 
-            if pixel == 255 && self.gfx[next_index] != 255 {
-                pixel_was_erased = true;
+            let index: usize = y * display_width + column;
+            let is_bit_set: bool = (sprite_row >> (sprite_width - 1 - i)) & 1 == 1;
+            if is_bit_set {
+                if self.gfx[index] & plane_bit != 0 {
+                    pixel_was_erased = true;
+                }
+                self.gfx[index] ^= plane_bit;
             }
         }
 
@@ -509,125 +1053,335 @@ impl Chip8 {
     // Skip next instruction if key with the value of Vx is pressed.
     // Checks the keyboard, and if the key corresponding to the value of Vx is currently in
     // the down position, PC is increased by 2.
-    fn skip_if_key_down(&mut self, reg_x: u8) {
-        validate_argument(reg_x, 0xF);
+    fn skip_if_key_down(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
         let key: u8 = self.cpu_registers[reg_x as usize];
-        let is_key_pressed = (self.key_states >> (15 - key)) & 0x1 == 1;
-        if is_key_pressed {
-            self.program_counter += 2;
+        if self.is_key_pressed(key) {
+            self.skip_next_instruction();
         }
+        Ok(())
     }
 
     // EXA1
     // Skip next instruction if key with the value of Vx is not pressed.
     // Checks the keyboard, and if the key corresponding to the value of Vx is currently in
     // the up position, PC is increased by 2.
-    fn skip_if_key_up(&mut self, reg_x: u8) {
-        validate_argument(reg_x, 0xF);
+    fn skip_if_key_up(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
         let key: u8 = self.cpu_registers[reg_x as usize];
-        let is_key_pressed = (self.key_states >> (15 - key)) & 0x1 == 1;
-        if !is_key_pressed {
-            self.program_counter += 2;
+        if !self.is_key_pressed(key) {
+            self.skip_next_instruction();
         }
+        Ok(())
     }
 
     // FX07
     // Set Vx = delay timer value.
     // The value of DT is placed into Vx.
-    fn read_delay_timer(&mut self, reg_x: u8) {
-        validate_argument(reg_x, 0xFF);
+    fn read_delay_timer(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xFF)?;
         self.cpu_registers[reg_x as usize] = self.delay_timer;
+        Ok(())
     }
 
     // FX0A
     // Wait for a key press, store the value of the key in Vx.
     // All execution stops until a key is pressed, then the value of that key is stored in Vx.
-    fn wait_for_key(&mut self, reg_x: u8) {
-        validate_argument(reg_x, 0x0F);
+    fn wait_for_key(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0x0F)?;
 
         // if the first four bits of wait_key_state are smaller than 0xF then
         // it is waiting for a key to be put in a register of that value
         // This is not part of the chip8 specification, simply a way to avoid dependency or
         // lifetime issues
         self.wait_key_state = reg_x;
+        Ok(())
     }
 
     // FX15
     // Set delay timer = Vx.
     // DT is set equal to the value of Vx.
-    fn set_delay_timer(&mut self, reg_x: u8) {
-        validate_argument(reg_x, 0xFF);
+    fn set_delay_timer(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xFF)?;
         self.delay_timer = self.cpu_registers[reg_x as usize];
+        Ok(())
     }
 
     // FX18
     // Set sound timer = Vx.
     // ST is set equal to the value of Vx.
-    fn set_sound_timer(&mut self, reg_x: u8) {
-        validate_argument(reg_x, 0xFF);
+    fn set_sound_timer(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xFF)?;
         self.sound_timer = self.cpu_registers[reg_x as usize];
+        Ok(())
     }
 
     // FX1E
     // Set I = I + Vx.
     // The values of I and Vx are added, and the results are stored in I.
-    fn index_reg_add(&mut self, reg_x: u8) {
-        validate_argument(reg_x, 0xFF);
-        self.index_register += self.cpu_registers[reg_x as usize] as u16;
+    fn index_reg_add(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xFF)?;
+        self.index_register = self
+            .index_register
+            .wrapping_add(self.cpu_registers[reg_x as usize] as u16);
+        Ok(())
     }
 
     // FX29
     // Set I = location of sprite for digit Vx.
     // The value of I is set to the location for the hexadecimal sprite corresponding
     // to the value of Vx.
-    fn set_index_to_character_address(&mut self, reg_x: u8) {
-        validate_argument(reg_x, 0xF);
+    fn set_index_to_character_address(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        let value: u8 = self.cpu_registers[reg_x as usize];
+        validate_argument(value, 0xF)?;
+        let address: usize = FONT_ADDRESS + value as usize * CHARACTER_SIZE;
+        self.index_register = address as u16;
+        Ok(())
+    }
+
+    // FX30 (SUPER-CHIP)
+    // Set I = location of the large sprite for digit Vx.
+    // The large sprites are 8x10 pixels and are drawn with DXYA.
+    fn set_index_to_big_character_address(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
         let value: u8 = self.cpu_registers[reg_x as usize];
-        validate_argument(value, 0xF);
-        let address: u16 = (value * 5) as u16;
-        self.index_register = address;
+        validate_argument(value, 0xF)?;
+        let address: usize = BIG_FONT_ADDRESS + value as usize * BIG_CHARACTER_SIZE;
+        self.index_register = address as u16;
+        Ok(())
     }
 
     // FX33
     // Store BCD representation of Vx in memory locations I, I+1, and I+2.
     // The interpreter takes the decimal value of Vx, and places the hundreds digit in memory
     // at location in I, the tens digit at location I+1, and the ones digit at location I+2.
-    fn store_bcd(&mut self, reg_x: u8) {
-        validate_argument(reg_x, 0xFF);
+    fn store_bcd(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xFF)?;
         let value: u8 = self.cpu_registers[reg_x as usize];
         let hundreds: u8 = value / 100;
         let tens: u8 = (value % 100) / 10;
         let digits: u8 = value % 10;
 
         let index: usize = self.index_register as usize;
-        self.memory[index] = hundreds;
-        self.memory[index + 1] = tens;
-        self.memory[index + 2] = digits;
+        self.check_address(index + 2)?;
+        self.write_byte(index, hundreds)?;
+        self.write_byte(index + 1, tens)?;
+        self.write_byte(index + 2, digits)?;
+        Ok(())
+    }
+
+    // FX55 and FX65 move I by X, X + 1 or not at all depending on the quirks
+    fn increment_index_after_load_store(&mut self, value: u8) {
+        let increment: u16 = match self.quirks.load_store_increments_i {
+            IndexIncrement::Unchanged => return,
+            IndexIncrement::ByX => value as u16,
+            IndexIncrement::ByXPlusOne => value as u16 + 1,
+        };
+        self.index_register = self.index_register.wrapping_add(increment);
     }
 
     // FX55
     // Store registers V0 through Vx in memory starting at location I.
     // The interpreter copies the values of registers V0 through Vx into memory,
     // starting at the address in I.
-    fn store_registers(&mut self, value: u8) {
-        validate_argument(value, 0xF);
+    fn store_registers(&mut self, value: u8) -> Result<(), Chip8Error> {
+        validate_argument(value, 0xF)?;
+        self.check_address(self.index_register as usize + value as usize)?;
         for i in 0..(value + 1) {
             let index = i as usize;
-            let memory_location = (self.index_register as usize + index) as usize;
-            self.memory[memory_location] = self.cpu_registers[index];
+            let memory_location = self.index_register as usize + index;
+            self.write_byte(memory_location, self.cpu_registers[index])?;
         }
+        self.increment_index_after_load_store(value);
+        Ok(())
     }
 
     // FX65
     // Read registers V0 through Vx from memory starting at location I.
     // The interpreter reads values from memory starting at location I into registers V0 through Vx.
-    fn read_memory(&mut self, value: u8) {
-        validate_argument(value, 0xF);
+    fn read_memory(&mut self, value: u8) -> Result<(), Chip8Error> {
+        validate_argument(value, 0xF)?;
+        self.check_address(self.index_register as usize + value as usize)?;
         for i in 0..(value + 1) {
             let index = i as usize;
-            let memory_location = (self.index_register as usize + index) as usize;
-            self.cpu_registers[index] = self.memory[memory_location];
+            let memory_location = self.index_register as usize + index;
+            self.cpu_registers[index] = self.read_byte(memory_location)?;
+        }
+        self.increment_index_after_load_store(value);
+        Ok(())
+    }
+
+    //
+    // SUPER-CHIP 1.1 instructions
+    // http://devernay.free.fr/hacks/chip8/schip.txt
+    //
+
+    // 00CN
+    // Scroll the display down by N pixels, the top rows are cleared.
+    fn scroll_down(&mut self, rows: u8) -> Result<(), Chip8Error> {
+        validate_argument(rows, 0xF)?;
+        self.scroll(0, rows as isize);
+        Ok(())
+    }
+
+    // 00DN (XO-CHIP)
+    // Scroll the display up by N pixels, the bottom rows are cleared.
+    fn scroll_up(&mut self, rows: u8) -> Result<(), Chip8Error> {
+        validate_argument(rows, 0xF)?;
+        self.scroll(0, -(rows as isize));
+        Ok(())
+    }
+
+    // 00FB
+    // Scroll the display right by 4 pixels, the leftmost columns are cleared.
+    fn scroll_right(&mut self) -> Result<(), Chip8Error> {
+        self.scroll(4, 0);
+        Ok(())
+    }
+
+    // 00FC
+    // Scroll the display left by 4 pixels, the rightmost columns are cleared.
+    fn scroll_left(&mut self) -> Result<(), Chip8Error> {
+        self.scroll(-4, 0);
+        Ok(())
+    }
+
+    // Moves the selected planes by (dx, dy) pixels, pixels coming from outside of the
+    // display are off.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width: isize = self.display_width() as isize;
+        let height: isize = self.display_height() as isize;
+        let source: Vec<u8> = self.gfx.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (source_x, source_y) = (x - dx, y - dy);
+                let is_inside: bool =
+                    source_x >= 0 && source_x < width && source_y >= 0 && source_y < height;
+                let moved: u8 = if is_inside {
+                    source[(source_y * width + source_x) as usize] & self.selected_planes
+                } else {
+                    0
+                };
+                let index: usize = (y * width + x) as usize;
+                self.gfx[index] = (self.gfx[index] & !self.selected_planes) | moved;
+            }
+        }
+    }
+
+    // 00FD
+    // Exit the interpreter.
+    fn exit(&mut self) -> Result<(), Chip8Error> {
+        self.has_exited = true;
+        Ok(())
+    }
+
+    // 00FE and 00FF
+    // Switch to the 64x32 low resolution or the 128x64 high resolution, the display is cleared.
+    fn set_high_resolution(&mut self, is_high_resolution: bool) -> Result<(), Chip8Error> {
+        self.is_high_resolution = is_high_resolution;
+        self.gfx = vec![0; self.display_width() * self.display_height()];
+        Ok(())
+    }
+
+    // FX75
+    // Store V0 through Vx in the RPL user flags.
+    fn store_flags(&mut self, value: u8) -> Result<(), Chip8Error> {
+        validate_argument(value, 0xF)?;
+        let count: usize = value as usize + 1;
+        self.rpl_flags[..count].copy_from_slice(&self.cpu_registers[..count]);
+        Ok(())
+    }
+
+    // FX85
+    // Read V0 through Vx from the RPL user flags.
+    fn read_flags(&mut self, value: u8) -> Result<(), Chip8Error> {
+        validate_argument(value, 0xF)?;
+        let count: usize = value as usize + 1;
+        self.cpu_registers[..count].copy_from_slice(&self.rpl_flags[..count]);
+        Ok(())
+    }
+
+    //
+    // XO-CHIP instructions
+    // https://johnearnest.github.io/Octo/docs/XO-ChipSpecification.html
+    //
+
+    // 5XY2
+    // Store registers Vx through Vy in memory starting at location I, in that order even when
+    // x is bigger than y. I is left unchanged.
+    fn store_register_range(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
+        let registers: Vec<usize> = Chip8::register_range(reg_x, reg_y);
+        let start: usize = self.index_register as usize;
+        self.check_address(start + registers.len() - 1)?;
+        for (offset, register) in registers.into_iter().enumerate() {
+            self.write_byte(start + offset, self.cpu_registers[register])?;
         }
+        Ok(())
+    }
+
+    // 5XY3
+    // Read registers Vx through Vy from memory starting at location I, in that order even when
+    // x is bigger than y. I is left unchanged.
+    fn read_register_range(&mut self, reg_x: u8, reg_y: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        validate_argument(reg_y, 0xF)?;
+        let registers: Vec<usize> = Chip8::register_range(reg_x, reg_y);
+        let start: usize = self.index_register as usize;
+        self.check_address(start + registers.len() - 1)?;
+        for (offset, register) in registers.into_iter().enumerate() {
+            self.cpu_registers[register] = self.read_byte(start + offset)?;
+        }
+        Ok(())
+    }
+
+    // The registers from x to y included, counting down when x is bigger than y
+    fn register_range(reg_x: u8, reg_y: u8) -> Vec<usize> {
+        if reg_x <= reg_y {
+            (reg_x as usize..=reg_y as usize).collect()
+        } else {
+            (reg_y as usize..=reg_x as usize).rev().collect()
+        }
+    }
+
+    // F000 NNNN
+    // Set I = NNNN, the 16 bit address stored in the two bytes after the instruction.
+    fn set_index_register_long(&mut self) -> Result<(), Chip8Error> {
+        self.index_register = self.fetch_next()?;
+        Ok(())
+    }
+
+    // FN01
+    // Select the planes drawn on by DXYN, cleared by 00E0 and scrolled, N being a bit mask
+    // of the planes.
+    fn select_planes(&mut self, planes: u8) -> Result<(), Chip8Error> {
+        validate_argument(planes, 0b11)?;
+        self.selected_planes = planes;
+        Ok(())
+    }
+
+    // F002
+    // Load the 16 bytes starting at location I in the audio pattern buffer.
+    fn load_audio_pattern(&mut self) -> Result<(), Chip8Error> {
+        let start: usize = self.index_register as usize;
+        self.check_address(start + AUDIO_PATTERN_SIZE - 1)?;
+        let mut pattern: [u8; AUDIO_PATTERN_SIZE] = [0; AUDIO_PATTERN_SIZE];
+        for (offset, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.read_byte(start + offset)?;
+        }
+        self.audio_pattern = Some(pattern);
+        Ok(())
+    }
+
+    // FX3A
+    // Set the pitch register to Vx, the audio pattern plays at 4000 * 2 ^ ((Vx - 64) / 48)
+    // bits per second.
+    fn set_pitch(&mut self, reg_x: u8) -> Result<(), Chip8Error> {
+        validate_argument(reg_x, 0xF)?;
+        self.pitch = self.cpu_registers[reg_x as usize];
+        Ok(())
     }
 }
 #[cfg(test)]
@@ -636,16 +1390,17 @@ mod tests {
 
     #[test]
     pub fn fetch_cycle_test() {
-        let mut c: Chip8 = Chip8::new();
+        let mut c: Chip8 = Chip8::new(Quirks::default());
         c.memory[c.program_counter as usize] = 0xA2;
         c.memory[(c.program_counter + 1) as usize] = 0xF0;
-        c.fetch_cycle();
+        c.fetch_cycle().unwrap();
         assert_eq!(c.index_register, 0x02F0);
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     pub fn draw_test() {
-        let mut c: Chip8 = Chip8::new();
+        let mut c: Chip8 = Chip8::new(Quirks::default());
         let mut gfx: [u8; 64 * 32] = [0; 64 * 32];
 
         assert_eq!(
@@ -663,13 +1418,13 @@ mod tests {
         c.memory[0x300] = 0xFF;
         c.memory[0x301] = 0x55;
 
-        c.execute_instruction(0x60FF);
-        c.execute_instruction(0x6155);
-        c.execute_instruction(0xA300);
-        c.execute_instruction(0xF155);
-        c.execute_instruction(0x6000);
-        c.execute_instruction(0x6100);
-        c.execute_instruction(0xD012);
+        c.execute_instruction(0x60FF).unwrap();
+        c.execute_instruction(0x6155).unwrap();
+        c.execute_instruction(0xA300).unwrap();
+        c.execute_instruction(0xF155).unwrap();
+        c.execute_instruction(0x6000).unwrap();
+        c.execute_instruction(0x6100).unwrap();
+        c.execute_instruction(0xD012).unwrap();
 
         for i in 0..8 {
             gfx[i] = 0xFF;
@@ -695,9 +1450,9 @@ mod tests {
 
     #[test]
     pub fn clear_screen_test() {
-        let mut c: Chip8 = Chip8::new();
+        let mut c: Chip8 = Chip8::new(Quirks::default());
         c.gfx[0] = 1;
-        c.clear_screen();
+        c.clear_screen().unwrap();
 
         let expected_gfx = [0; 64 * 32];
         assert_eq!(
@@ -713,51 +1468,99 @@ mod tests {
 
     #[test]
     pub fn load_index_test() {
-        let mut c: Chip8 = Chip8::new();
-        c.execute_instruction(0xA123);
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.execute_instruction(0xA123).unwrap();
         assert_eq!(c.index_register, 0x0123);
     }
 
     #[test]
-    #[should_panic]
     pub fn bad_opcode_test() {
-        let mut c: Chip8 = Chip8::new();
-        c.execute_instruction(0x68);
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0x00, 0x68]).unwrap();
+        assert_eq!(
+            c.fetch_cycle(),
+            Err(Chip8Error::UnknownOpcode {
+                pc: 0x200,
+                opcode: 0x68
+            })
+        );
+    }
+
+    #[test]
+    pub fn stack_underflow_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        assert_eq!(
+            c.execute_instruction(0x00EE),
+            Err(Chip8Error::StackUnderflow)
+        );
+    }
+
+    #[test]
+    pub fn stack_overflow_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        for _ in 0..STACK_SIZE {
+            c.execute_instruction(0x2200).unwrap();
+        }
+        assert_eq!(
+            c.execute_instruction(0x2200),
+            Err(Chip8Error::StackOverflow)
+        );
+    }
+
+    #[test]
+    pub fn memory_out_of_bounds_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.execute_instruction(0xAFFE).unwrap();
+        assert_eq!(
+            c.execute_instruction(0xF033),
+            Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 })
+        );
+        assert_eq!(
+            c.execute_instruction(0xF255),
+            Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 })
+        );
+        assert_eq!(c.execute_instruction(0xF165), Ok(()));
+        assert_eq!(
+            c.load_rom(&[0; MEMORY_SIZE - PROGRAM_START + 1]),
+            Err(Chip8Error::MemoryOutOfBounds { addr: MEMORY_SIZE })
+        );
     }
 
     #[test]
     pub fn jump_to_address_plus_v0_test() {
-        let mut c: Chip8 = Chip8::new();
+        let mut c: Chip8 = Chip8::new(Quirks::default());
         c.cpu_registers[0] = 0x68;
-        c.jump_to_address_plus_v0(0x0123);
+        c.jump_to_address_plus_v0(0x0123).unwrap();
         assert_eq!(c.program_counter, 0x0123 + 0x68);
-        c.jump_to_address_plus_v0(0x0433);
+        c.jump_to_address_plus_v0(0x0433).unwrap();
         assert_eq!(c.program_counter, 0x0433 + 0x68);
     }
 
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     pub fn set_index_register_test() {
-        let mut c: Chip8 = Chip8::new();
-        c.set_index_register(100 as u16);
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.set_index_register(100 as u16).unwrap();
         assert_eq!(c.index_register, 100);
     }
 
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     pub fn add_test() {
-        let mut c: Chip8 = Chip8::new();
-        c.add(10 as u8, 12 as u8);
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.add(10 as u8, 12 as u8).unwrap();
         assert_eq!(c.cpu_registers[10 as usize], 12);
 
-        c.add(10 as u8, 3 as u8);
+        c.add(10 as u8, 3 as u8).unwrap();
         assert_eq!(c.cpu_registers[10 as usize], 15);
 
-        c.add(4 as u8, 3 as u8);
+        c.add(4 as u8, 3 as u8).unwrap();
         assert_eq!(c.cpu_registers[4 as usize], 3);
     }
 
     #[test]
     pub fn add_registers_test() {
-        let mut c: Chip8 = Chip8::new();
+        let mut c: Chip8 = Chip8::new(Quirks::default());
         c.cpu_registers[0] = 4;
         c.cpu_registers[2] = 3;
         c.cpu_registers[4] = 3;
@@ -766,23 +1569,23 @@ mod tests {
 
         assert_eq!(c.cpu_registers[0xF], 0); // todo: remove this eager test
 
-        c.add_registers(0, 2);
+        c.add_registers(0, 2).unwrap();
         assert_eq!(c.cpu_registers[0], 7);
         assert_eq!(c.cpu_registers[2], 3);
         assert_eq!(c.cpu_registers[0xF], 0);
 
-        c.add_registers(6, 5);
+        c.add_registers(6, 5).unwrap();
         assert_eq!(c.cpu_registers[6], 0);
         assert_eq!(c.cpu_registers[0xF], 1);
 
-        c.add_registers(6, 5);
+        c.add_registers(6, 5).unwrap();
         assert_eq!(c.cpu_registers[6], 1);
         assert_eq!(c.cpu_registers[0xF], 0);
     }
 
     #[test]
     pub fn sub_registers_test() {
-        let mut c: Chip8 = Chip8::new();
+        let mut c: Chip8 = Chip8::new(Quirks::default());
         c.cpu_registers[0] = 4;
         c.cpu_registers[1] = 4;
         c.cpu_registers[2] = 3;
@@ -790,37 +1593,658 @@ mod tests {
         c.cpu_registers[5] = 1;
         c.cpu_registers[6] = 0xFF;
 
-        c.sub_registers(0, 2);
+        c.sub_registers(0, 2).unwrap();
         assert_eq!(c.cpu_registers[0], 1);
         assert_eq!(c.cpu_registers[2], 3);
         assert_eq!(c.cpu_registers[0xF], 1);
 
-        c.sub_registers(4, 5);
+        c.sub_registers(4, 5).unwrap();
         assert_eq!(c.cpu_registers[4], 2);
         assert_eq!(c.cpu_registers[5], 1);
         assert_eq!(c.cpu_registers[0xF], 1);
 
-        c.sub_registers(2, 1);
+        c.sub_registers(2, 1).unwrap();
         assert_eq!(c.cpu_registers[1], 4);
         assert_eq!(c.cpu_registers[2], 255);
         assert_eq!(c.cpu_registers[0xF], 0);
     }
 
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     pub fn bit_or_test() {
-        let mut c: Chip8 = Chip8::new();
+        let mut c: Chip8 = Chip8::new(Quirks::default());
         c.cpu_registers[0] = 4;
         c.cpu_registers[2] = 3;
         c.cpu_registers[4] = 3;
         c.cpu_registers[5] = 1;
 
-        c.bit_or(2 as u8, 0 as u8);
+        c.bit_or(2 as u8, 0 as u8).unwrap();
         assert_eq!(c.cpu_registers[2], 7);
 
-        c.bit_or(3 as u8, 4 as u8);
+        c.bit_or(3 as u8, 4 as u8).unwrap();
         assert_eq!(c.cpu_registers[3], 3);
 
-        c.bit_or(5 as u8, 4 as u8);
+        c.bit_or(5 as u8, 4 as u8).unwrap();
         assert_eq!(c.cpu_registers[5], 3);
     }
+
+    #[test]
+    pub fn font_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        assert_eq!(
+            &c.memory[FONT_ADDRESS..(FONT_ADDRESS + FONT_SIZE)],
+            &DEFAULT_FONT[..]
+        );
+
+        c.execute_instruction(0x6A0B).unwrap();
+        c.execute_instruction(0xFA29).unwrap();
+        assert_eq!(
+            c.index_register as usize,
+            FONT_ADDRESS + 0xB * CHARACTER_SIZE
+        );
+        assert_eq!(
+            &c.memory[c.index_register as usize..c.index_register as usize + 5],
+            &[0xE0, 0x90, 0xE0, 0x90, 0xE0]
+        );
+
+        let custom_font: Font = [0xAA; FONT_SIZE];
+        c.set_font(&custom_font);
+        assert_eq!(c.memory[FONT_ADDRESS + FONT_SIZE - 1], 0xAA);
+    }
+
+    #[test]
+    pub fn load_rom_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0x60, 0x2A]).unwrap();
+        c.fetch_cycle().unwrap();
+        assert_eq!(c.cpu_registers[0], 0x2A);
+        assert_eq!(c.program_counter, 0x202);
+    }
+
+    #[test]
+    pub fn press_release_key_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.press_key(0x0).unwrap();
+        c.press_key(0xA).unwrap();
+        assert!(c.is_key_pressed(0x0));
+        assert!(c.is_key_pressed(0xA));
+        assert!(!c.is_key_pressed(0xB));
+        assert_eq!(c.key_states, 0x8020);
+
+        c.release_key(0x0).unwrap();
+        assert!(!c.is_key_pressed(0x0));
+        assert_eq!(c.key_states, 0x0020);
+    }
+
+    #[test]
+    pub fn wait_for_key_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0xF3, 0x0A, 0x60, 0x01]).unwrap();
+        c.fetch_cycle().unwrap();
+        assert!(c.is_waiting_for_key());
+
+        c.run_frame(10).unwrap();
+        assert!(c.is_waiting_for_key());
+        assert_eq!(c.program_counter, 0x202);
+
+        c.press_key(0xC).unwrap();
+        c.press_key(0x7).unwrap();
+        c.fetch_cycle().unwrap();
+        assert!(!c.is_waiting_for_key());
+        assert_eq!(c.cpu_registers[3], 0x7);
+
+        c.fetch_cycle().unwrap();
+        assert_eq!(c.cpu_registers[0], 0x01);
+    }
+
+    #[test]
+    pub fn run_frame_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0x70, 0x01, 0x70, 0x01, 0x70, 0x01]).unwrap();
+        c.sound_timer = 2;
+        c.run_frame(3).unwrap();
+        assert_eq!(c.cpu_registers[0], 3);
+        assert!(c.is_sound_active());
+        c.run_frame(0).unwrap();
+        assert!(!c.is_sound_active());
+    }
+
+    #[test]
+    pub fn has_sounded_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Sets the sound timer to 1, then loops
+        c.load_rom(&[0x60, 0x01, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+        c.run_frame(2).unwrap();
+        assert!(c.has_sounded());
+        assert!(!c.is_sound_active());
+        c.run_frame(1).unwrap();
+        assert!(!c.has_sounded());
+    }
+
+    #[test]
+    pub fn shift_quirk_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::modern());
+        c.cpu_registers[0] = 0b0000_0101;
+        c.cpu_registers[1] = 0b1000_0000;
+        c.execute_instruction(0x8016).unwrap();
+        assert_eq!(c.cpu_registers[0], 0b0000_0010);
+        assert_eq!(c.cpu_registers[0xF], 1);
+        c.execute_instruction(0x801E).unwrap();
+        assert_eq!(c.cpu_registers[0], 0b0000_0100);
+        assert_eq!(c.cpu_registers[0xF], 0);
+
+        let mut c: Chip8 = Chip8::new(Quirks::cosmac_vip());
+        c.cpu_registers[0] = 0b0000_0101;
+        c.cpu_registers[1] = 0b1000_0000;
+        c.execute_instruction(0x8016).unwrap();
+        assert_eq!(c.cpu_registers[0], 0b0100_0000);
+        assert_eq!(c.cpu_registers[0xF], 0);
+        c.execute_instruction(0x801E).unwrap();
+        assert_eq!(c.cpu_registers[0], 0);
+        assert_eq!(c.cpu_registers[0xF], 1);
+    }
+
+    #[test]
+    pub fn load_store_quirk_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::modern());
+        c.execute_instruction(0xA300).unwrap();
+        c.execute_instruction(0xF255).unwrap();
+        assert_eq!(c.index_register, 0x300);
+
+        let mut c: Chip8 = Chip8::new(Quirks::cosmac_vip());
+        c.execute_instruction(0xA300).unwrap();
+        c.execute_instruction(0xF255).unwrap();
+        assert_eq!(c.index_register, 0x303);
+        c.execute_instruction(0xF065).unwrap();
+        assert_eq!(c.index_register, 0x304);
+
+        let mut c: Chip8 = Chip8::new(Quirks::chip48());
+        c.execute_instruction(0xA300).unwrap();
+        c.execute_instruction(0xF255).unwrap();
+        assert_eq!(c.index_register, 0x302);
+        c.execute_instruction(0xF065).unwrap();
+        assert_eq!(c.index_register, 0x302);
+
+        // The three settings survive save states
+        let mut restored: Chip8 = Chip8::new(Quirks::default());
+        restored.load_state(&c.save_state()).unwrap();
+        assert_eq!(restored.quirks(), Quirks::chip48());
+    }
+
+    #[test]
+    pub fn jump_quirk_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::chip48());
+        c.cpu_registers[0] = 0x10;
+        c.cpu_registers[3] = 0x02;
+        c.execute_instruction(0xB320).unwrap();
+        assert_eq!(c.program_counter, 0x322);
+    }
+
+    #[test]
+    pub fn vf_reset_quirk_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::cosmac_vip());
+        c.cpu_registers[0xF] = 1;
+        c.execute_instruction(0x8011).unwrap();
+        assert_eq!(c.cpu_registers[0xF], 0);
+
+        let mut c: Chip8 = Chip8::new(Quirks::modern());
+        c.cpu_registers[0xF] = 1;
+        c.execute_instruction(0x8011).unwrap();
+        assert_eq!(c.cpu_registers[0xF], 1);
+    }
+
+    #[test]
+    pub fn clip_sprites_quirk_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::modern());
+        c.memory[0x300] = 0xFF;
+        c.memory[0x301] = 0xFF;
+        c.execute_instruction(0x603C).unwrap();
+        c.execute_instruction(0x611F).unwrap();
+        c.execute_instruction(0xA300).unwrap();
+        c.execute_instruction(0xD012).unwrap();
+        assert_eq!(c.gfx[31 * DISPLAY_WIDTH + 63], 1);
+        assert_eq!(c.gfx[31 * DISPLAY_WIDTH], 1);
+        assert_eq!(c.gfx[63], 1);
+        assert_eq!(c.gfx[0], 1);
+
+        let mut c: Chip8 = Chip8::new(Quirks::cosmac_vip());
+        c.memory[0x300] = 0xFF;
+        c.memory[0x301] = 0xFF;
+        c.execute_instruction(0x603C).unwrap();
+        c.execute_instruction(0x611F).unwrap();
+        c.execute_instruction(0xA300).unwrap();
+        c.execute_instruction(0xD012).unwrap();
+        assert_eq!(c.gfx[31 * DISPLAY_WIDTH + 63], 1);
+        assert_eq!(c.gfx[31 * DISPLAY_WIDTH], 0);
+        assert_eq!(c.gfx[63], 0);
+        assert_eq!(c.gfx[0], 0);
+    }
+
+    #[test]
+    pub fn display_wait_quirk_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::cosmac_vip());
+        c.load_rom(&[0xD0, 0x01, 0xD0, 0x01]).unwrap();
+        assert_eq!(c.fetch_cycle(), Ok(StepOutcome::Executed));
+        assert_eq!(c.fetch_cycle(), Ok(StepOutcome::WaitingForVBlank));
+        assert_eq!(c.program_counter, 0x202);
+
+        c.run_frame(0).unwrap();
+        assert_eq!(c.fetch_cycle(), Ok(StepOutcome::Executed));
+        assert_eq!(c.program_counter, 0x204);
+    }
+
+    #[test]
+    pub fn timers_tick_once_per_frame_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0x12, 0x00]).unwrap();
+        c.delay_timer = 60;
+        c.sound_timer = 3;
+
+        for _ in 0..30 {
+            c.run_frame(DEFAULT_CYCLES_PER_FRAME).unwrap();
+        }
+        assert_eq!(c.delay_timer, 30);
+        assert!(!c.is_sound_active());
+
+        for _ in 0..10 {
+            c.run_frame(100).unwrap();
+        }
+        assert_eq!(c.delay_timer, 20);
+    }
+
+    #[test]
+    pub fn high_resolution_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::super_chip());
+        c.gfx[0] = 1;
+        c.execute_instruction(0x00FF).unwrap();
+        assert!(c.is_high_resolution());
+        assert_eq!(c.display_width(), HIRES_DISPLAY_WIDTH);
+        assert_eq!(c.display_height(), HIRES_DISPLAY_HEIGHT);
+        assert_eq!(
+            c.framebuffer().len(),
+            HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT
+        );
+        assert!(c.framebuffer().iter().all(|pixel| *pixel == 0));
+
+        c.execute_instruction(0x00FE).unwrap();
+        assert_eq!(c.framebuffer().len(), DISPLAY_WIDTH * DISPLAY_HEIGHT);
+    }
+
+    #[test]
+    pub fn draw_large_sprite_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::super_chip());
+        c.execute_instruction(0x00FF).unwrap();
+        for i in 0..32 {
+            c.memory[0x300 + i] = if i % 2 == 0 { 0xFF } else { 0x01 };
+        }
+        c.execute_instruction(0x6070).unwrap();
+        c.execute_instruction(0x6102).unwrap();
+        c.execute_instruction(0xA300).unwrap();
+        c.execute_instruction(0xD010).unwrap();
+
+        let width: usize = HIRES_DISPLAY_WIDTH;
+        for row in 2..18 {
+            assert_eq!(c.gfx[row * width + 112], 1);
+            assert_eq!(c.gfx[row * width + 119], 1);
+            assert_eq!(c.gfx[row * width + 120], 0);
+            assert_eq!(c.gfx[row * width + 127], 1);
+        }
+        assert_eq!(c.gfx[18 * width + 112], 0);
+        assert_eq!(c.gfx[width + 112], 0);
+        assert_eq!(c.cpu_registers[0xF], 0);
+    }
+
+    #[test]
+    pub fn scroll_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::super_chip());
+        c.gfx[0] = 1;
+        c.execute_instruction(0x00C3).unwrap();
+        assert_eq!(c.gfx[0], 0);
+        assert_eq!(c.gfx[3 * DISPLAY_WIDTH], 1);
+
+        c.execute_instruction(0x00FB).unwrap();
+        assert_eq!(c.gfx[3 * DISPLAY_WIDTH], 0);
+        assert_eq!(c.gfx[3 * DISPLAY_WIDTH + 4], 1);
+
+        c.execute_instruction(0x00FC).unwrap();
+        c.execute_instruction(0x00FC).unwrap();
+        assert!(c.gfx.iter().all(|pixel| *pixel == 0));
+    }
+
+    #[test]
+    pub fn big_font_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::super_chip());
+        c.execute_instruction(0x6509).unwrap();
+        c.execute_instruction(0xF530).unwrap();
+        assert_eq!(
+            c.index_register as usize,
+            BIG_FONT_ADDRESS + 9 * BIG_CHARACTER_SIZE
+        );
+        assert_eq!(c.memory[c.index_register as usize + 2], 0xC3);
+    }
+
+    #[test]
+    pub fn rpl_flags_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::super_chip());
+        c.cpu_registers[0] = 1;
+        c.cpu_registers[1] = 2;
+        c.cpu_registers[2] = 3;
+        c.execute_instruction(0xF175).unwrap();
+        c.cpu_registers = [0; 16];
+        c.execute_instruction(0xF285).unwrap();
+        assert_eq!(&c.cpu_registers[..3], &[1, 2, 0]);
+    }
+
+    #[test]
+    pub fn exit_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::super_chip());
+        c.load_rom(&[0x00, 0xFD, 0x60, 0x01]).unwrap();
+        assert_eq!(c.fetch_cycle(), Ok(StepOutcome::Exited));
+        assert!(c.has_exited());
+        assert_eq!(c.fetch_cycle(), Ok(StepOutcome::Exited));
+        assert_eq!(c.cpu_registers[0], 0);
+    }
+
+    #[test]
+    pub fn xo_chip_memory_test() {
+        let mut c: Chip8 = Chip8::new_xo_chip(Quirks::xo_chip());
+        assert!(c.is_xo_chip());
+        c.load_rom(&[0xF0, 0x00, 0xFF, 0xF0, 0x60, 0x2A, 0xF0, 0x55])
+            .unwrap();
+        c.fetch_cycle().unwrap();
+        assert_eq!(c.index_register, 0xFFF0);
+        assert_eq!(c.program_counter, 0x204);
+        c.fetch_cycle().unwrap();
+        c.fetch_cycle().unwrap();
+        assert_eq!(c.memory[0xFFF0], 0x2A);
+
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        assert!(!c.is_xo_chip());
+        assert!(c
+            .load_rom(&[0; XO_CHIP_MEMORY_SIZE - PROGRAM_START])
+            .is_err());
+    }
+
+    #[test]
+    pub fn skip_long_instruction_test() {
+        let mut c: Chip8 = Chip8::new_xo_chip(Quirks::xo_chip());
+        c.load_rom(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x60, 0x01])
+            .unwrap();
+        c.fetch_cycle().unwrap();
+        assert_eq!(c.program_counter, 0x206);
+
+        // F0 00 is a regular word to other machines, and not an instruction
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x60, 0x01])
+            .unwrap();
+        c.fetch_cycle().unwrap();
+        assert_eq!(c.program_counter, 0x204);
+        c.program_counter = 0x202;
+        assert_eq!(
+            c.fetch_cycle(),
+            Err(Chip8Error::UnknownOpcode {
+                pc: 0x202,
+                opcode: 0xF000
+            })
+        );
+    }
+
+    #[test]
+    pub fn end_of_memory_test() {
+        let mut c: Chip8 = Chip8::new_xo_chip(Quirks::xo_chip());
+        c.index_register = 0xFFFF;
+        c.cpu_registers[0] = 0x2A;
+        c.execute_instruction(0xF055).unwrap();
+        assert_eq!(c.memory[0xFFFF], 0x2A);
+        assert_eq!(c.index_register, 0x0000);
+
+        c.index_register = 0xFFFF;
+        c.execute_instruction(0xF065).unwrap();
+        assert_eq!(c.cpu_registers[0], 0x2A);
+        assert_eq!(c.index_register, 0x0000);
+
+        c.index_register = 0xFFFF;
+        assert!(c.execute_instruction(0xF155).is_err());
+        assert!(c.execute_instruction(0xF165).is_err());
+
+        c.memory[0xFFFE] = 0x60;
+        c.memory[0xFFFF] = 0x01;
+        c.program_counter = 0xFFFE;
+        c.fetch_cycle().unwrap();
+        assert_eq!(c.cpu_registers[0], 0x01);
+        assert_eq!(c.program_counter, 0x0000);
+
+        // Skipping F000 NNNN at the end of memory
+        c.memory[0xFFFC] = 0x30;
+        c.memory[0xFFFD] = 0x01;
+        c.memory[0xFFFE] = 0xF0;
+        c.memory[0xFFFF] = 0x00;
+        c.program_counter = 0xFFFC;
+        c.fetch_cycle().unwrap();
+        assert_eq!(c.program_counter, 0x0002);
+    }
+
+    #[test]
+    pub fn register_range_test() {
+        let mut c: Chip8 = Chip8::new_xo_chip(Quirks::xo_chip());
+        c.cpu_registers[2] = 2;
+        c.cpu_registers[3] = 3;
+        c.cpu_registers[4] = 4;
+        c.execute_instruction(0xA300).unwrap();
+        c.execute_instruction(0x5242).unwrap();
+        assert_eq!(&c.memory[0x300..0x303], &[2, 3, 4]);
+        assert_eq!(c.index_register, 0x300);
+
+        c.execute_instruction(0x5A83).unwrap();
+        assert_eq!(&c.cpu_registers[8..11], &[4, 3, 2]);
+    }
+
+    #[test]
+    pub fn planes_test() {
+        let mut c: Chip8 = Chip8::new_xo_chip(Quirks::xo_chip());
+        c.memory[0x300] = 0x80;
+        c.memory[0x301] = 0xC0;
+        c.execute_instruction(0xA300).unwrap();
+        c.execute_instruction(0xF301).unwrap();
+        c.execute_instruction(0xD001).unwrap();
+        assert_eq!(c.gfx[0], 0b11);
+        assert_eq!(c.gfx[1], 0b10);
+
+        c.execute_instruction(0xF201).unwrap();
+        c.execute_instruction(0x00E0).unwrap();
+        assert_eq!(c.gfx[0], 0b01);
+        assert_eq!(c.gfx[1], 0);
+
+        c.execute_instruction(0xF101).unwrap();
+        c.execute_instruction(0xD001).unwrap();
+        assert_eq!(c.gfx[0], 0);
+        assert_eq!(c.cpu_registers[0xF], 1);
+    }
+
+    #[test]
+    pub fn audio_pattern_test() {
+        let mut c: Chip8 = Chip8::new_xo_chip(Quirks::xo_chip());
+        assert_eq!(c.audio_pattern(), None);
+        assert_eq!(c.audio_pattern_rate(), 4000.0);
+
+        for i in 0..AUDIO_PATTERN_SIZE {
+            c.memory[0x300 + i] = i as u8;
+        }
+        c.execute_instruction(0xA300).unwrap();
+        c.execute_instruction(0xF002).unwrap();
+        assert_eq!(c.audio_pattern().unwrap()[15], 15);
+
+        c.execute_instruction(0x6070).unwrap();
+        c.execute_instruction(0xF03A).unwrap();
+        assert_eq!(c.audio_pattern_rate(), 8000.0);
+    }
+
+    #[test]
+    pub fn memory_tracing_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0xA3, 0x00, 0x60, 0xFE, 0xF0, 0x33]).unwrap();
+        c.fetch_cycle().unwrap();
+        c.fetch_cycle().unwrap();
+        c.set_memory_tracing(true);
+        c.fetch_cycle().unwrap();
+        assert_eq!(
+            c.memory_accesses(),
+            &[
+                MemoryAccess {
+                    addr: 0x300,
+                    kind: AccessKind::Write,
+                    old_value: 0,
+                    new_value: 2
+                },
+                MemoryAccess {
+                    addr: 0x301,
+                    kind: AccessKind::Write,
+                    old_value: 0,
+                    new_value: 5
+                },
+                MemoryAccess {
+                    addr: 0x302,
+                    kind: AccessKind::Write,
+                    old_value: 0,
+                    new_value: 4
+                },
+            ]
+        );
+
+        c.execute_instruction(0xF065).unwrap();
+        assert_eq!(c.memory_accesses().len(), 4);
+        assert_eq!(c.memory_accesses()[3].kind, AccessKind::Read);
+        assert_eq!(c.memory_accesses()[3].new_value, 2);
+
+        c.set_memory_tracing(false);
+        c.execute_instruction(0xF233).unwrap();
+        assert!(c.memory_accesses().is_empty());
+    }
+
+    #[test]
+    pub fn save_state_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::cosmac_vip());
+        // Calls a subroutine that sets V0, points I at the font and draws a digit
+        c.load_rom(&[0x22, 0x04, 0x00, 0x00, 0x60, 0x07, 0xF0, 0x29, 0xD0, 0x05])
+            .unwrap();
+        for _ in 0..5 {
+            c.fetch_cycle().unwrap();
+        }
+        c.execute_instruction(0x00FF).unwrap();
+        c.execute_instruction(0xD005).unwrap();
+        c.delay_timer = 30;
+        c.press_key(0xA).unwrap();
+        let state: Vec<u8> = c.save_state();
+
+        let mut restored: Chip8 = Chip8::new(Quirks::default());
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.quirks(), Quirks::cosmac_vip());
+        assert_eq!(restored.program_counter, c.program_counter);
+        assert_eq!(restored.stack_data, [0x202]);
+        assert_eq!(restored.framebuffer(), c.framebuffer());
+        assert!(restored.is_high_resolution());
+        assert!(restored.is_key_pressed(0xA));
+        assert_eq!(restored.delay_timer(), 30);
+    }
+
+    #[test]
+    pub fn load_invalid_state_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0x60, 0x2A]).unwrap();
+        let mut state: Vec<u8> = c.save_state();
+        let last: usize = state.len() - 1;
+        state[last] ^= 0xFF;
+
+        let mut other: Chip8 = Chip8::new(Quirks::default());
+        assert_eq!(
+            other.load_state(&state),
+            Err(Chip8Error::InvalidSaveState {
+                reason: "checksum mismatch"
+            })
+        );
+        assert_eq!(other.memory[PROGRAM_START], 0);
+    }
+
+    #[test]
+    pub fn seeded_rand_test() {
+        let rom: [u8; 6] = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0x0F];
+        let run = |seed: u64| -> [u8; 3] {
+            let mut c: Chip8 = Chip8::new(Quirks::default());
+            c.set_seed(seed);
+            c.load_rom(&rom).unwrap();
+            c.run_frame(3).unwrap();
+            [c.cpu_registers[0], c.cpu_registers[1], c.cpu_registers[2]]
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+        assert_eq!(run(7)[2] & 0xF0, 0);
+
+        // The generator is part of the save state
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.set_seed(7);
+        c.load_rom(&rom).unwrap();
+        c.fetch_cycle().unwrap();
+        let state: Vec<u8> = c.save_state();
+        c.fetch_cycle().unwrap();
+        let v1: u8 = c.cpu_registers[1];
+        c.load_state(&state).unwrap();
+        c.fetch_cycle().unwrap();
+        assert_eq!(c.cpu_registers[1], v1);
+    }
+
+    #[test]
+    pub fn load_out_of_range_state_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.wait_key_state = 0x20;
+        let state: Vec<u8> = c.save_state();
+        let mut restored: Chip8 = Chip8::new(Quirks::default());
+        assert_eq!(
+            restored.load_state(&state),
+            Err(Chip8Error::InvalidSaveState {
+                reason: "unknown key wait register"
+            })
+        );
+        assert!(!restored.is_waiting_for_key());
+
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.selected_planes = 0b100;
+        let state: Vec<u8> = c.save_state();
+        assert_eq!(
+            restored.load_state(&state),
+            Err(Chip8Error::InvalidSaveState {
+                reason: "unknown planes"
+            })
+        );
+
+        // FX0A waiting on VF is valid
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.wait_key_state = 0xF;
+        restored.load_state(&c.save_state()).unwrap();
+        restored.press_key(3).unwrap();
+        restored.fetch_cycle().unwrap();
+        assert_eq!(restored.cpu_registers[0xF], 3);
+    }
+
+    #[test]
+    pub fn load_state_without_generator_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0x60, 0x2A]).unwrap();
+        c.fetch_cycle().unwrap();
+
+        // A valid header around a payload missing the generator state
+        let state: Vec<u8> = c.save_state();
+        let mut writer: StateWriter = StateWriter::new();
+        writer.bytes(&state[crate::chip8_state::HEADER_SIZE..state.len() - 8]);
+        let state: Vec<u8> = writer.finish();
+
+        let mut restored: Chip8 = Chip8::new(Quirks::default());
+        assert_eq!(
+            restored.load_state(&state),
+            Err(Chip8Error::InvalidSaveState {
+                reason: "truncated"
+            })
+        );
+        assert_eq!(restored.cpu_registers[0], 0);
+    }
 }
@@ -0,0 +1,967 @@
+use crate::chip8::PROGRAM_START;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+// Assembles the Octo language into ROMs loaded at PROGRAM_START.
+// https://github.com/JohnEarnest/Octo/blob/gh-pages/docs/Manual.md
+// Supported: labels, :alias, :const, :byte, :org, :call, :macro, loop/while/again,
+// if/then and if/begin/else/end, plus every CHIP-8, SUPER-CHIP and XO-CHIP statement.
+// The comparison operators <, >, <= and >= of if are not supported.
+// Programs start at the main label, like in Octo 0x200 holds a jump to it unless the
+// program starts with it.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblerError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssemblerError {}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    // How many macro expansions deep the token comes from, 0 for the source itself
+    depth: usize,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+// A block being assembled, closed by again or end
+enum Flow {
+    // The jumps out of the loop left by while are patched by again
+    Loop { start: usize, exits: Vec<usize> },
+    // The jump over the block is patched by else or end
+    If { jump: usize },
+}
+
+// An address that was used before its label was defined
+struct Fixup {
+    offset: usize,
+    label: String,
+    line: usize,
+    is_long: bool,
+}
+
+// Past this many macros expanded within each other, a macro is assumed to call itself
+const MAX_MACRO_DEPTH: usize = 256;
+
+struct Assembler {
+    tokens: Vec<Token>,
+    position: usize,
+    rom: Vec<u8>,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i32>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    flow: Vec<(Flow, usize)>,
+}
+
+// Assembles Octo source code into a ROM, the errors point to the faulty line.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblerError> {
+    let mut assembler = Assembler {
+        tokens: tokenize(source),
+        position: 0,
+        rom: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        flow: Vec::new(),
+    };
+    while assembler.position < assembler.tokens.len() {
+        assembler.statement()?;
+    }
+    assembler.finish()
+}
+
+// Splits the source in words, # starts a comment up to the end of the line
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let code: &str = line.split('#').next().unwrap_or("");
+        for word in code.split_whitespace() {
+            tokens.push(Token {
+                text: String::from(word),
+                line: index + 1,
+                depth: 0,
+            });
+        }
+    }
+    tokens
+}
+
+// Decimal, 0x hexadecimal or 0b binary, with an optional minus sign
+fn parse_number(text: &str) -> Option<i32> {
+    let (is_negative, digits): (bool, &str) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value: i32 = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if is_negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => {
+            digit.to_digit(16).map(|digit| digit as u8)
+        }
+        _ => None,
+    }
+}
+
+impl Assembler {
+    fn error<T>(&self, line: usize, message: String) -> Result<T, AssemblerError> {
+        Err(AssemblerError { line, message })
+    }
+
+    fn last_line(&self) -> usize {
+        self.tokens.last().map_or(1, |token| token.line)
+    }
+
+    fn next_token(&mut self) -> Result<Token, AssemblerError> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token.clone())
+            }
+            None => self.error(self.last_line(), String::from("unexpected end of file")),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens
+            .get(self.position)
+            .map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AssemblerError> {
+        let token: Token = self.next_token()?;
+        if token.text != text {
+            return self.error(
+                token.line,
+                format!("expected {} but found {}", text, token.text),
+            );
+        }
+        Ok(())
+    }
+
+    fn address(&self) -> usize {
+        PROGRAM_START + self.rom.len()
+    }
+
+    // Called before anything is written at an address, the first time makes room at 0x200
+    // for the jump to main, unless main is already defined there
+    fn reserve_main_jump(&mut self) {
+        if !self.rom.is_empty() || self.labels.contains_key("main") {
+            return;
+        }
+        let line: usize = self
+            .tokens
+            .get(self.position.saturating_sub(1))
+            .map_or(1, |token| token.line);
+        self.fixups.push(Fixup {
+            offset: 0,
+            label: String::from("main"),
+            line,
+            is_long: false,
+        });
+        self.rom.extend_from_slice(&[0x10, 0x00]);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        self.reserve_main_jump();
+        self.rom.push(byte);
+    }
+
+    fn emit_opcode(&mut self, opcode: u16) {
+        self.reserve_main_jump();
+        self.rom.push((opcode >> 8) as u8);
+        self.rom.push(opcode as u8);
+    }
+
+    // ORs a 12 bit address into the opcode at offset in the ROM
+    fn patch(&mut self, offset: usize, address: usize, line: usize) -> Result<(), AssemblerError> {
+        if address > 0xFFF {
+            return self.error(line, format!("jump target {:#X} is past 0xFFF", address));
+        }
+        self.rom[offset] |= (address >> 8) as u8 & 0x0F;
+        self.rom[offset + 1] |= address as u8;
+        Ok(())
+    }
+
+    fn register(&mut self) -> Result<u8, AssemblerError> {
+        let token: Token = self.next_token()?;
+        match self.find_register(&token.text) {
+            Some(register) => Ok(register),
+            None => self.error(
+                token.line,
+                format!("expected a register but found {}", token.text),
+            ),
+        }
+    }
+
+    fn find_register(&self, text: &str) -> Option<u8> {
+        parse_register(text).or_else(|| self.aliases.get(text).copied())
+    }
+
+    fn find_value(&self, text: &str) -> Option<i32> {
+        parse_number(text)
+            .or_else(|| self.constants.get(text).copied())
+            .or_else(|| self.labels.get(text).map(|address| *address as i32))
+    }
+
+    fn value(&mut self, min: i32, max: i32) -> Result<i32, AssemblerError> {
+        let token: Token = self.next_token()?;
+        match self.find_value(&token.text) {
+            Some(value) if value >= min && value <= max => Ok(value),
+            Some(value) => self.error(
+                token.line,
+                format!("{} does not fit between {} and {}", value, min, max),
+            ),
+            None => self.error(token.line, format!("unknown value {}", token.text)),
+        }
+    }
+
+    // Accepts negative bytes, -1 is 0xFF
+    fn byte(&mut self) -> Result<u8, AssemblerError> {
+        Ok(self.value(-128, 0xFF)? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AssemblerError> {
+        Ok(self.value(0, 0xF)? as u8)
+    }
+
+    // Emits base with the 12 or 16 bit address of the next token, labels may be defined
+    // later on
+    fn emit_with_address(&mut self, base: u16, is_long: bool) -> Result<(), AssemblerError> {
+        let token: Token = self.next_token()?;
+        let max: i32 = if is_long { 0xFFFF } else { 0xFFF };
+        let address: i32 = match self.find_value(&token.text) {
+            Some(address) if address >= 0 && address <= max => address,
+            Some(address) => {
+                return self.error(
+                    token.line,
+                    format!("address {:#X} is out of range", address),
+                )
+            }
+            None => {
+                self.fixups.push(Fixup {
+                    offset: self.rom.len(),
+                    label: token.text,
+                    line: token.line,
+                    is_long,
+                });
+                0
+            }
+        };
+        if is_long {
+            self.emit_opcode(base);
+            self.emit_opcode(address as u16);
+        } else {
+            self.emit_opcode(base | address as u16);
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AssemblerError> {
+        let token: Token = self.next_token()?;
+        match token.text.as_str() {
+            ":" => {
+                let name: Token = self.next_token()?;
+                if self.labels.contains_key(&name.text) {
+                    return self.error(name.line, format!("label {} is defined twice", name.text));
+                }
+                if name.text != "main" {
+                    self.reserve_main_jump();
+                }
+                self.labels.insert(name.text, self.address());
+            }
+            ":alias" => {
+                let name: Token = self.next_token()?;
+                let register: u8 = self.register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name: Token = self.next_token()?;
+                let value: i32 = self.value(i32::MIN, i32::MAX)?;
+                self.constants.insert(name.text, value);
+            }
+            ":byte" => {
+                let byte: u8 = self.byte()?;
+                self.emit_byte(byte);
+            }
+            ":org" => {
+                let address: usize = self.value(0, 0xFFFF)? as usize;
+                self.reserve_main_jump();
+                if address < self.address() {
+                    return self.error(
+                        token.line,
+                        format!("cannot go back to {:#X} with :org", address),
+                    );
+                }
+                self.rom.resize(address - PROGRAM_START, 0);
+            }
+            ":call" => self.emit_with_address(0x2000, false)?,
+            ":macro" => self.define_macro()?,
+            "clear" => self.emit_opcode(0x00E0),
+            "return" | ";" => self.emit_opcode(0x00EE),
+            "scroll-down" => {
+                let rows: u8 = self.nibble()?;
+                self.emit_opcode(0x00C0 | rows as u16);
+            }
+            "scroll-up" => {
+                let rows: u8 = self.nibble()?;
+                self.emit_opcode(0x00D0 | rows as u16);
+            }
+            "scroll-right" => self.emit_opcode(0x00FB),
+            "scroll-left" => self.emit_opcode(0x00FC),
+            "exit" => self.emit_opcode(0x00FD),
+            "lores" => self.emit_opcode(0x00FE),
+            "hires" => self.emit_opcode(0x00FF),
+            "jump" => self.emit_with_address(0x1000, false)?,
+            "jump0" => self.emit_with_address(0xB000, false)?,
+            "sprite" => {
+                let x: u8 = self.register()?;
+                let y: u8 = self.register()?;
+                let rows: u8 = self.nibble()?;
+                self.emit_opcode(0xD000 | (x as u16) << 8 | (y as u16) << 4 | rows as u16);
+            }
+            "bcd" => self.register_statement(0xF033)?,
+            "saveflags" => self.register_statement(0xF075)?,
+            "loadflags" => self.register_statement(0xF085)?,
+            "save" => self.save_or_load(0xF055, 0x5002)?,
+            "load" => self.save_or_load(0xF065, 0x5003)?,
+            "plane" => {
+                let planes: u8 = self.value(0, 3)? as u8;
+                self.emit_opcode(0xF001 | (planes as u16) << 8);
+            }
+            "audio" => self.emit_opcode(0xF002),
+            "delay" => {
+                self.expect(":=")?;
+                self.register_statement(0xF015)?;
+            }
+            "buzzer" => {
+                self.expect(":=")?;
+                self.register_statement(0xF018)?;
+            }
+            "pitch" => {
+                self.expect(":=")?;
+                self.register_statement(0xF03A)?;
+            }
+            "i" => self.index_statement()?,
+            "loop" => self.flow.push((
+                Flow::Loop {
+                    start: self.address(),
+                    exits: Vec::new(),
+                },
+                token.line,
+            )),
+            "while" => {
+                let (skip_if_true, _) = self.condition()?;
+                self.emit_opcode(skip_if_true);
+                let jump: usize = self.rom.len();
+                self.emit_opcode(0x1000);
+                match self.flow.iter_mut().rev().find_map(|(flow, _)| match flow {
+                    Flow::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                }) {
+                    Some(exits) => exits.push(jump),
+                    None => return self.error(token.line, String::from("while outside of a loop")),
+                }
+            }
+            "again" => match self.flow.pop() {
+                Some((Flow::Loop { start, exits }, _)) => {
+                    let jump: usize = self.rom.len();
+                    self.emit_opcode(0x1000);
+                    self.patch(jump, start, token.line)?;
+                    let end: usize = self.address();
+                    for exit in exits {
+                        self.patch(exit, end, token.line)?;
+                    }
+                }
+                _ => return self.error(token.line, String::from("again without loop")),
+            },
+            "if" => self.if_statement()?,
+            "else" => match self.flow.pop() {
+                Some((Flow::If { jump }, line)) => {
+                    let else_jump: usize = self.rom.len();
+                    self.emit_opcode(0x1000);
+                    self.patch(jump, self.address(), token.line)?;
+                    self.flow.push((Flow::If { jump: else_jump }, line));
+                }
+                _ => return self.error(token.line, String::from("else without if begin")),
+            },
+            "end" => match self.flow.pop() {
+                Some((Flow::If { jump }, _)) => self.patch(jump, self.address(), token.line)?,
+                _ => return self.error(token.line, String::from("end without if begin")),
+            },
+            text => {
+                if let Some(register) = self.find_register(text) {
+                    self.register_assignment(register)?;
+                } else if let Some(number) = parse_number(text) {
+                    if !(-128..=0xFF).contains(&number) {
+                        return self.error(token.line, format!("{} is not a byte", number));
+                    }
+                    self.emit_byte(number as u8);
+                } else if self.macros.contains_key(text) {
+                    self.expand_macro(&token)?;
+                } else if text.starts_with(':') || text.starts_with('{') || text.starts_with('}') {
+                    return self.error(token.line, format!("unsupported directive {}", text));
+                } else {
+                    // A bare name calls the subroutine at that label
+                    self.position -= 1;
+                    self.emit_with_address(0x2000, false)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Opcodes taking a single register in their X nibble
+    fn register_statement(&mut self, base: u16) -> Result<(), AssemblerError> {
+        let x: u8 = self.register()?;
+        self.emit_opcode(base | (x as u16) << 8);
+        Ok(())
+    }
+
+    // save vx / load vx use I, save vx - vy / load vx - vy are the XO-CHIP ranges
+    fn save_or_load(&mut self, base: u16, range_base: u16) -> Result<(), AssemblerError> {
+        let x: u8 = self.register()?;
+        if self.peek() == Some("-") {
+            self.position += 1;
+            let y: u8 = self.register()?;
+            self.emit_opcode(range_base | (x as u16) << 8 | (y as u16) << 4);
+        } else {
+            self.emit_opcode(base | (x as u16) << 8);
+        }
+        Ok(())
+    }
+
+    fn index_statement(&mut self) -> Result<(), AssemblerError> {
+        let operator: Token = self.next_token()?;
+        match operator.text.as_str() {
+            ":=" => match self.peek() {
+                Some("long") => {
+                    self.position += 1;
+                    self.emit_with_address(0xF000, true)
+                }
+                Some("hex") => {
+                    self.position += 1;
+                    self.register_statement(0xF029)
+                }
+                Some("bighex") => {
+                    self.position += 1;
+                    self.register_statement(0xF030)
+                }
+                _ => self.emit_with_address(0xA000, false),
+            },
+            "+=" => self.register_statement(0xF01E),
+            _ => self.error(
+                operator.line,
+                format!("unknown operator {} for i", operator.text),
+            ),
+        }
+    }
+
+    fn register_assignment(&mut self, x: u8) -> Result<(), AssemblerError> {
+        let operator: Token = self.next_token()?;
+        let x_bits: u16 = (x as u16) << 8;
+        let rhs: Option<u8> = self.peek().and_then(|text| self.find_register(text));
+        if let Some(y) = rhs {
+            self.position += 1;
+            let alu: u16 = match operator.text.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => {
+                    return self.error(
+                        operator.line,
+                        format!("unknown operator {} between registers", operator.text),
+                    )
+                }
+            };
+            self.emit_opcode(0x8000 | x_bits | (y as u16) << 4 | alu);
+            return Ok(());
+        }
+
+        match (operator.text.as_str(), self.peek()) {
+            (":=", Some("random")) => {
+                self.position += 1;
+                let mask: u8 = self.byte()?;
+                self.emit_opcode(0xC000 | x_bits | mask as u16);
+            }
+            (":=", Some("delay")) => {
+                self.position += 1;
+                self.emit_opcode(0xF007 | x_bits);
+            }
+            (":=", Some("key")) => {
+                self.position += 1;
+                self.emit_opcode(0xF00A | x_bits);
+            }
+            (":=", _) => {
+                let byte: u8 = self.byte()?;
+                self.emit_opcode(0x6000 | x_bits | byte as u16);
+            }
+            ("+=", _) => {
+                let byte: u8 = self.byte()?;
+                self.emit_opcode(0x7000 | x_bits | byte as u16);
+            }
+            ("-=", _) => {
+                let byte: u8 = self.byte()?;
+                self.emit_opcode(0x7000 | x_bits | byte.wrapping_neg() as u16);
+            }
+            _ => {
+                return self.error(
+                    operator.line,
+                    format!("unknown operator {} with a constant", operator.text),
+                )
+            }
+        }
+        Ok(())
+    }
+
+    // Parses the condition of if and while, returns the opcodes skipping the next instruction
+    // when the condition is true and when it is false
+    fn condition(&mut self) -> Result<(u16, u16), AssemblerError> {
+        let x_bits: u16 = (self.register()? as u16) << 8;
+        let operator: Token = self.next_token()?;
+        match operator.text.as_str() {
+            "key" => return Ok((0xE09E | x_bits, 0xE0A1 | x_bits)),
+            "-key" => return Ok((0xE0A1 | x_bits, 0xE09E | x_bits)),
+            "==" | "!=" => {}
+            _ => {
+                return self.error(
+                    operator.line,
+                    format!("unsupported comparison {}", operator.text),
+                )
+            }
+        }
+
+        let (if_equal, if_not_equal): (u16, u16) =
+            match self.peek().and_then(|text| self.find_register(text)) {
+                Some(y) => {
+                    self.position += 1;
+                    let y_bits: u16 = (y as u16) << 4;
+                    (0x5000 | x_bits | y_bits, 0x9000 | x_bits | y_bits)
+                }
+                None => {
+                    let byte: u16 = self.byte()? as u16;
+                    (0x3000 | x_bits | byte, 0x4000 | x_bits | byte)
+                }
+            };
+        if operator.text == "==" {
+            Ok((if_equal, if_not_equal))
+        } else {
+            Ok((if_not_equal, if_equal))
+        }
+    }
+
+    // if cond then statement, or if cond begin ... else ... end
+    fn if_statement(&mut self) -> Result<(), AssemblerError> {
+        let line: usize = self.tokens[self.position - 1].line;
+        let (skip_if_true, skip_if_false) = self.condition()?;
+        let keyword: Token = self.next_token()?;
+        match keyword.text.as_str() {
+            "then" => self.emit_opcode(skip_if_false),
+            "begin" => {
+                self.emit_opcode(skip_if_true);
+                let jump: usize = self.rom.len();
+                self.emit_opcode(0x1000);
+                self.flow.push((Flow::If { jump }, line));
+            }
+            _ => {
+                return self.error(
+                    keyword.line,
+                    format!("expected then or begin but found {}", keyword.text),
+                )
+            }
+        }
+        Ok(())
+    }
+
+    // :macro name params... { body }
+    fn define_macro(&mut self) -> Result<(), AssemblerError> {
+        let name: Token = self.next_token()?;
+        let mut params: Vec<String> = Vec::new();
+        loop {
+            let token: Token = self.next_token()?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+
+        let mut body: Vec<Token> = Vec::new();
+        let mut depth: usize = 1;
+        loop {
+            let token: Token = self.next_token()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    // Replaces the macro call with its body, the parameters substituted by the arguments
+    fn expand_macro(&mut self, call: &Token) -> Result<(), AssemblerError> {
+        let depth: usize = call.depth + 1;
+        if depth > MAX_MACRO_DEPTH {
+            return self.error(call.line, format!("macro {} never ends", call.text));
+        }
+
+        let macro_definition: &Macro = &self.macros[&call.text];
+        let param_count: usize = macro_definition.params.len();
+        if self.position + param_count > self.tokens.len() {
+            return self.error(
+                call.line,
+                format!("macro {} expects {} arguments", call.text, param_count),
+            );
+        }
+        let args: &[Token] = &self.tokens[self.position..(self.position + param_count)];
+        let expansion: Vec<Token> = macro_definition
+            .body
+            .iter()
+            .map(|token| {
+                let text: String = match macro_definition
+                    .params
+                    .iter()
+                    .position(|param| *param == token.text)
+                {
+                    Some(index) => args[index].text.clone(),
+                    None => token.text.clone(),
+                };
+                // Errors inside the expansion point to the call
+                Token {
+                    text,
+                    line: call.line,
+                    depth,
+                }
+            })
+            .collect();
+
+        self.tokens
+            .splice(self.position..(self.position + param_count), expansion);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, AssemblerError> {
+        if let Some((flow, line)) = self.flow.last() {
+            let message: &str = match flow {
+                Flow::Loop { .. } => "loop without again",
+                Flow::If { .. } => "if begin without end",
+            };
+            return self.error(*line, String::from(message));
+        }
+        if !self.labels.contains_key("main") {
+            return self.error(
+                self.last_line(),
+                String::from("the program has no main label"),
+            );
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let address: usize = match self.labels.get(&fixup.label) {
+                Some(address) => *address,
+                None => return self.error(fixup.line, format!("unknown label {}", fixup.label)),
+            };
+            if fixup.is_long {
+                self.rom[fixup.offset + 2] = (address >> 8) as u8;
+                self.rom[fixup.offset + 3] = address as u8;
+            } else if address > 0xFFF {
+                return self.error(
+                    fixup.line,
+                    format!("label {} is past 0xFFF, use i := long", fixup.label),
+                );
+            } else {
+                self.patch(fixup.offset, address, fixup.line)?;
+            }
+        }
+        Ok(self.rom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+    use crate::Quirks;
+
+    #[test]
+    pub fn statements_test() {
+        let rom: Vec<u8> = assemble(
+            "
+            : main
+            clear
+            v0 := 0xFF  v1 := v0  v2 += 3  v3 -= 1  v4 ^= v5  v6 := random 0x0F
+            i := 0x300  i += v1  i := hex v2  bcd v3  save v4  load v5
+            sprite v0 v1 5  delay := v0  buzzer := v1  v7 := key  v8 := delay
+            ",
+        )
+        .unwrap();
+        let opcodes: Vec<u16> = rom
+            .chunks(2)
+            .map(|word| (word[0] as u16) << 8 | word[1] as u16)
+            .collect();
+        assert_eq!(
+            opcodes,
+            [
+                0x00E0, 0x60FF, 0x8100, 0x7203, 0x73FF, 0x8453, 0xC60F, 0xA300, 0xF11E, 0xF229,
+                0xF333, 0xF455, 0xF565, 0xD015, 0xF015, 0xF118, 0xF70A, 0xF807
+            ]
+        );
+    }
+
+    #[test]
+    pub fn labels_test() {
+        let rom: Vec<u8> = assemble(
+            "
+            : main
+                draw-it   # forward call
+                jump main
+            : draw-it
+                i := sprite-data
+                ;
+            : sprite-data
+                0xF0 0x90 :byte 0b11110000
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            rom,
+            [0x22, 0x04, 0x12, 0x00, 0xA2, 0x08, 0x00, 0xEE, 0xF0, 0x90, 0xF0]
+        );
+    }
+
+    #[test]
+    pub fn alias_const_test() {
+        let rom: Vec<u8> = assemble(
+            "
+            : main
+            :alias score v3
+            :const START 42
+            score := START
+            ",
+        )
+        .unwrap();
+        assert_eq!(rom, [0x63, 42]);
+    }
+
+    #[test]
+    pub fn control_flow_test() {
+        let rom: Vec<u8> = assemble(
+            "
+            : main
+            loop
+                if v0 == 5 then v1 := 1
+                while v2 != v3
+                if v4 key begin
+                    v5 := 1
+                else
+                    v5 := 2
+                end
+            again
+            ",
+        )
+        .unwrap();
+        let opcodes: Vec<u16> = rom
+            .chunks(2)
+            .map(|word| (word[0] as u16) << 8 | word[1] as u16)
+            .collect();
+        assert_eq!(
+            opcodes,
+            [
+                0x4005, // 200: skip if v0 != 5
+                0x6101, // 202
+                0x9230, // 204: skip the exit if v2 != v3
+                0x1214, // 206: exit the loop
+                0xE49E, // 208: skip the jump to else if v4 is pressed
+                0x1210, // 20A
+                0x6501, // 20C
+                0x1212, // 20E: jump over else
+                0x6502, // 210
+                0x1200, // 212: again
+            ]
+        );
+    }
+
+    #[test]
+    pub fn macro_test() {
+        let rom: Vec<u8> = assemble(
+            "
+            : main
+            :macro set-both a b { va := a  vb := b }
+            set-both 1 2
+            set-both 3 v1
+            ",
+        )
+        .unwrap();
+        assert_eq!(rom, [0x6A, 1, 0x6B, 2, 0x6A, 3, 0x8B, 0x10]);
+    }
+
+    #[test]
+    pub fn macro_depth_test() {
+        assert_eq!(
+            assemble(": main\n:macro forever { clear forever }\nforever"),
+            Err(AssemblerError {
+                line: 3,
+                message: String::from("macro forever never ends")
+            })
+        );
+        // Many expansions are fine as long as they do not nest
+        let source: String = format!(": main :macro nothing {{ }} {}", "nothing ".repeat(20_000));
+        assert_eq!(assemble(&source), Ok(Vec::new()));
+    }
+
+    #[test]
+    pub fn xo_chip_test() {
+        let rom: Vec<u8> = assemble(
+            "
+            : main
+            i := long data  plane 3  audio  save v1 - v4  load v4 - v1  pitch := v2
+            :org 0x300
+            : data
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            rom[..14],
+            [0xF0, 0x00, 0x03, 0x00, 0xF3, 0x01, 0xF0, 0x02, 0x51, 0x42, 0x54, 0x13, 0xF2, 0x3A]
+        );
+        assert_eq!(rom.len(), 0x100);
+    }
+
+    #[test]
+    pub fn errors_test() {
+        assert_eq!(
+            assemble(": main v0 := 1\njump nowhere"),
+            Err(AssemblerError {
+                line: 2,
+                message: String::from("unknown label nowhere")
+            })
+        );
+        assert_eq!(assemble(": main v0 := 256").unwrap_err().line, 1);
+        assert_eq!(assemble(": main\nloop v0 := 1").unwrap_err().line, 2);
+        assert!(assemble(": main if v0 > 1 then clear").is_err());
+        assert!(assemble(": main vg := 1").is_err());
+        assert!(assemble(": a : a").is_err());
+        assert_eq!(
+            assemble("v0 := 1\nclear"),
+            Err(AssemblerError {
+                line: 2,
+                message: String::from("the program has no main label")
+            })
+        );
+    }
+
+    #[test]
+    pub fn main_test() {
+        // Subroutines may come before main, 0x200 then jumps to it
+        let rom: Vec<u8> = assemble(
+            "
+            : draw-player
+                sprite v0 v1 5
+                return
+            : main
+                v0 := 1
+                draw-player
+                exit
+            ",
+        )
+        .unwrap();
+        assert_eq!(rom[0..6], [0x12, 0x06, 0xD0, 0x15, 0x00, 0xEE]);
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&rom).unwrap();
+        c.run_frame(10).unwrap();
+        assert!(c.has_exited());
+        assert_eq!(c.registers()[0], 1);
+        assert!(c.stack().is_empty());
+
+        // No jump is needed when the program starts with main
+        assert_eq!(assemble(": main clear"), Ok(vec![0x00, 0xE0]));
+        assert_eq!(
+            assemble(":org 0x300 : main clear").unwrap()[0..2],
+            [0x13, 0x00]
+        );
+    }
+
+    #[test]
+    pub fn flow_past_0xfff_test() {
+        assert_eq!(
+            assemble(": main :org 0x1000\nloop\nv0 := 1\nagain"),
+            Err(AssemblerError {
+                line: 4,
+                message: String::from("jump target 0x1000 is past 0xFFF")
+            })
+        );
+        assert_eq!(
+            assemble(": main if v0 == 1 begin\n:org 0xFFE\nclear\nend")
+                .unwrap_err()
+                .line,
+            4
+        );
+        assert!(assemble(": main loop\nwhile v0 != 1\n:org 0xFFE\nclear\nagain").is_err());
+        assert!(assemble(": main if v0 == 1 begin\n:org 0xFFC\nclear\nend").is_ok());
+    }
+
+    #[test]
+    pub fn run_assembled_program_test() {
+        let rom: Vec<u8> = assemble(
+            "
+            : main
+                v0 := 0
+                loop
+                    v0 += 1
+                    if v0 != 10 then
+                again
+                i := result
+                save v0
+                exit
+            : result
+                0
+            ",
+        )
+        .unwrap();
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&rom).unwrap();
+        c.run_frame(100).unwrap();
+        assert!(c.has_exited());
+        assert_eq!(c.memory()[0x200 + rom.len() - 1], 10);
+    }
+}
@@ -0,0 +1,469 @@
+use crate::chip8::{Chip8, FRAMES_PER_SECOND, HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH};
+use crate::chip8_image::BitWriter;
+use crate::chip8_palette::Palette;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+// Captures are scale times the size of the high resolution display, so no pixel is lost.
+// Low resolution frames are drawn with pixels twice as big, so the size never changes.
+
+// Pixels of a GIF frame are XO-CHIP plane indexes into a 4 colour table
+const GIF_MIN_CODE_SIZE: u32 = 2;
+const GIF_MAX_CODE: u16 = 4096;
+// Browsers slow down frames shown for less than 2 hundredths of a second, those are dropped
+const GIF_MIN_DELAY: u64 = 2;
+
+fn capture_size(scale: usize) -> (usize, usize) {
+    (HIRES_DISPLAY_WIDTH * scale, HIRES_DISPLAY_HEIGHT * scale)
+}
+
+// Planes lit at each pixel of a width by height picture of the display
+fn stretch_framebuffer(chip8: &Chip8, width: usize, height: usize) -> Vec<u8> {
+    let display_width: usize = chip8.display_width();
+    let display_height: usize = chip8.display_height();
+    let framebuffer: &[u8] = chip8.framebuffer();
+    let mut pixels: Vec<u8> = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row: usize = y * display_height / height * display_width;
+        for x in 0..width {
+            pixels.push(framebuffer[row + x * display_width / width] & 0b11);
+        }
+    }
+    pixels
+}
+
+// Time since the start of the capture in hundredths of a second, the unit of GIF delays
+fn centiseconds(frames: u64) -> u64 {
+    (frames * 100 + FRAMES_PER_SECOND as u64 / 2) / FRAMES_PER_SECOND as u64
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend_from_slice(&(value.min(u16::MAX as usize) as u16).to_le_bytes());
+}
+
+// https://www.w3.org/Graphics/GIF/spec-gif89a.txt appendix F, codes grow from 3 to 12 bits
+// and the table starts over once full
+fn lzw_compress(pixels: &[u8]) -> Vec<u8> {
+    let clear_code: u16 = 1 << GIF_MIN_CODE_SIZE;
+    let end_code: u16 = clear_code + 1;
+    let mut writer = BitWriter::new();
+    let mut codes: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code: u16 = end_code + 1;
+    let mut code_size: u32 = GIF_MIN_CODE_SIZE + 1;
+    writer.write_bits(clear_code as u32, code_size);
+
+    // Code of the pixels read but not written yet
+    let mut prefix: Option<u16> = None;
+    for &pixel in pixels {
+        let current: u16 = match prefix {
+            Some(current) => current,
+            None => {
+                prefix = Some(pixel as u16);
+                continue;
+            }
+        };
+        if let Some(&code) = codes.get(&(current, pixel)) {
+            prefix = Some(code);
+            continue;
+        }
+        writer.write_bits(current as u32, code_size);
+        if next_code < GIF_MAX_CODE {
+            codes.insert((current, pixel), next_code);
+            next_code += 1;
+            if next_code > 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+        } else {
+            writer.write_bits(clear_code as u32, code_size);
+            codes.clear();
+            next_code = end_code + 1;
+            code_size = GIF_MIN_CODE_SIZE + 1;
+        }
+        prefix = Some(pixel as u16);
+    }
+    if let Some(current) = prefix {
+        writer.write_bits(current as u32, code_size);
+    }
+    writer.write_bits(end_code as u32, code_size);
+    writer.finish()
+}
+
+struct PendingFrame {
+    pixels: Vec<u8>,
+    // Frame of the capture it was first shown at
+    start: u64,
+}
+
+// Builds a looping animated GIF, one frame per call to capture.
+// A frame is only written once the display changes, for as long as it was shown, and only
+// the rectangle that changed since the previous one is stored.
+pub struct GifRecorder {
+    width: usize,
+    height: usize,
+    gif: Vec<u8>,
+    // The picture once the written frames are drawn, empty before the first one
+    shown: Vec<u8>,
+    pending: Option<PendingFrame>,
+    frames: u64,
+    frame_count: usize,
+}
+
+impl GifRecorder {
+    pub fn new(palette: &Palette, scale: usize) -> GifRecorder {
+        let (width, height) = capture_size(scale);
+        let mut gif: Vec<u8> = b"GIF89a".to_vec();
+        push_u16(&mut gif, width);
+        push_u16(&mut gif, height);
+        // Global colour table of 2^(1 + 1) colours, background 0, square pixels
+        gif.extend_from_slice(&[0xF1, 0x00, 0x00]);
+        for color in palette.colors.iter() {
+            gif.extend_from_slice(color);
+        }
+        // Loops forever
+        gif.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        gif.extend_from_slice(b"NETSCAPE2.0");
+        gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+
+        GifRecorder {
+            width,
+            height,
+            gif,
+            shown: Vec::new(),
+            pending: None,
+            frames: 0,
+            frame_count: 0,
+        }
+    }
+
+    // Call once per frame, after the frame ran
+    pub fn capture(&mut self, chip8: &Chip8) {
+        let pixels: Vec<u8> = stretch_framebuffer(chip8, self.width, self.height);
+        let now: u64 = self.frames;
+        self.frames += 1;
+
+        let pending: PendingFrame = match self.pending.take() {
+            None => PendingFrame { pixels, start: now },
+            Some(pending) if pending.pixels == pixels => pending,
+            Some(pending) if centiseconds(now) - centiseconds(pending.start) < GIF_MIN_DELAY => {
+                PendingFrame {
+                    pixels,
+                    start: pending.start,
+                }
+            }
+            Some(pending) => {
+                let delay: u64 = centiseconds(now) - centiseconds(pending.start);
+                self.write_frame(&pending.pixels, delay);
+                PendingFrame { pixels, start: now }
+            }
+        };
+        self.pending = Some(pending);
+    }
+
+    // Frames of the GIF, identical frames in a row count once
+    pub fn frame_count(&self) -> usize {
+        self.frame_count + self.pending.is_some() as usize
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        if let Some(pending) = self.pending.take() {
+            let delay: u64 = centiseconds(self.frames) - centiseconds(pending.start);
+            self.write_frame(&pending.pixels, delay.max(GIF_MIN_DELAY));
+        }
+        self.gif.push(0x3B);
+        self.gif
+    }
+
+    fn write_frame(&mut self, pixels: &[u8], delay: u64) {
+        let (left, top, right, bottom) = self.changed_rectangle(pixels);
+
+        // Graphic control extension: the frame stays in place under the next one
+        self.gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x04]);
+        push_u16(&mut self.gif, delay as usize);
+        self.gif.extend_from_slice(&[0x00, 0x00]);
+
+        self.gif.push(0x2C);
+        push_u16(&mut self.gif, left);
+        push_u16(&mut self.gif, top);
+        push_u16(&mut self.gif, right - left);
+        push_u16(&mut self.gif, bottom - top);
+        self.gif.push(0x00);
+
+        let rectangle: Vec<u8> = pixels
+            .chunks(self.width)
+            .take(bottom)
+            .skip(top)
+            .flat_map(|row| row[left..right].iter().copied())
+            .collect();
+        self.gif.push(GIF_MIN_CODE_SIZE as u8);
+        for block in lzw_compress(&rectangle).chunks(255) {
+            self.gif.push(block.len() as u8);
+            self.gif.extend_from_slice(block);
+        }
+        self.gif.push(0x00);
+
+        self.shown = pixels.to_vec();
+        self.frame_count += 1;
+    }
+
+    // Left, top, right and bottom of the smallest rectangle holding the pixels that differ
+    // from the picture shown, the right and bottom edges excluded
+    fn changed_rectangle(&self, pixels: &[u8]) -> (usize, usize, usize, usize) {
+        if self.shown.is_empty() {
+            return (0, 0, self.width, self.height);
+        }
+        let mut rectangle: Option<(usize, usize, usize, usize)> = None;
+        for (index, (old, new)) in self.shown.iter().zip(pixels.iter()).enumerate() {
+            if old == new {
+                continue;
+            }
+            let (x, y) = (index % self.width, index / self.width);
+            rectangle = Some(match rectangle {
+                None => (x, y, x + 1, y + 1),
+                Some((left, top, right, bottom)) => {
+                    (left.min(x), top.min(y), right.max(x + 1), bottom.max(y + 1))
+                }
+            });
+        }
+        // A frame is still needed to keep the delay
+        rectangle.unwrap_or((0, 0, 1, 1))
+    }
+}
+
+// Writes every frame as raw RGBA pixels, row after row, for an external encoder such as
+// ffmpeg -f rawvideo -pixel_format rgba -video_size WIDTHxHEIGHT -framerate 60 -i -
+pub struct RawRecorder<W: Write> {
+    writer: W,
+    palette: Palette,
+    width: usize,
+    height: usize,
+}
+
+impl<W: Write> RawRecorder<W> {
+    pub fn new(writer: W, palette: &Palette, scale: usize) -> RawRecorder<W> {
+        let (width, height) = capture_size(scale);
+        RawRecorder {
+            writer,
+            palette: *palette,
+            width,
+            height,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Call once per frame, after the frame ran
+    pub fn capture(&mut self, chip8: &Chip8) -> io::Result<()> {
+        let rgba: Vec<u8> = stretch_framebuffer(chip8, self.width, self.height)
+            .iter()
+            .flat_map(|planes| {
+                let [red, green, blue] = self.palette.color(*planes);
+                [red, green, blue, 0xFF]
+            })
+            .collect();
+        self.writer.write_all(&rgba)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_quirks::Quirks;
+
+    // Reads codes back the way a GIF decoder does
+    fn lzw_decompress(data: &[u8]) -> Vec<u8> {
+        let clear_code: usize = 1 << GIF_MIN_CODE_SIZE;
+        let mut bit_position: usize = 0;
+        let mut read_code = |size: usize| -> usize {
+            let mut code: usize = 0;
+            for bit in 0..size {
+                let position: usize = bit_position + bit;
+                code |= ((data[position / 8] >> (position % 8)) as usize & 1) << bit;
+            }
+            bit_position += size;
+            code
+        };
+
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut code_size: usize = 0;
+        let mut previous: Option<usize> = None;
+        let mut pixels: Vec<u8> = Vec::new();
+        loop {
+            let code: usize = read_code(code_size.max(GIF_MIN_CODE_SIZE as usize + 1));
+            if code == clear_code {
+                table = (0..clear_code).map(|pixel| vec![pixel as u8]).collect();
+                table.push(Vec::new());
+                table.push(Vec::new());
+                code_size = GIF_MIN_CODE_SIZE as usize + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear_code + 1 {
+                return pixels;
+            }
+            let entry: Vec<u8> = match previous {
+                Some(previous) if code == table.len() => {
+                    let mut entry: Vec<u8> = table[previous].clone();
+                    entry.push(table[previous][0]);
+                    entry
+                }
+                _ => table[code].clone(),
+            };
+            if let Some(previous) = previous {
+                let mut new_entry: Vec<u8> = table[previous].clone();
+                new_entry.push(entry[0]);
+                table.push(new_entry);
+                if table.len() == 1 << code_size && code_size < 12 {
+                    code_size += 1;
+                }
+            }
+            pixels.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    #[test]
+    pub fn lzw_compress_test() {
+        let pixels: Vec<u8> = vec![0, 0, 0, 1, 1, 2, 3, 0, 0, 0, 0, 0, 1];
+        assert_eq!(lzw_decompress(&lzw_compress(&pixels)), pixels);
+
+        // Noisy enough to fill the table several times
+        let mut state: u32 = 1;
+        let pixels: Vec<u8> = (0..40000)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8 & 0b11
+            })
+            .collect();
+        assert_eq!(lzw_decompress(&lzw_compress(&pixels)), pixels);
+    }
+
+    struct GifFrame {
+        delay: u16,
+        // Left, top, width and height
+        rectangle: [u16; 4],
+        pixels: Vec<u8>,
+    }
+
+    fn read_gif_frames(gif: &[u8]) -> Vec<GifFrame> {
+        let read_u16 = |index: usize| u16::from_le_bytes([gif[index], gif[index + 1]]);
+        // Concatenates the sub-blocks starting at index, returns the index after them
+        let read_blocks = |mut index: usize| -> (Vec<u8>, usize) {
+            let mut data: Vec<u8> = Vec::new();
+            while gif[index] != 0 {
+                let length: usize = gif[index] as usize;
+                data.extend_from_slice(&gif[index + 1..index + 1 + length]);
+                index += length + 1;
+            }
+            (data, index + 1)
+        };
+
+        let mut frames: Vec<GifFrame> = Vec::new();
+        let mut delay: u16 = 0;
+        // After the header, the screen descriptor and the 4 colours
+        let mut index: usize = 6 + 7 + 12;
+        loop {
+            match gif[index] {
+                0x21 => {
+                    if gif[index + 1] == 0xF9 {
+                        delay = read_u16(index + 4);
+                    }
+                    index = read_blocks(index + 2).1;
+                }
+                0x2C => {
+                    let rectangle: [u16; 4] = [
+                        read_u16(index + 1),
+                        read_u16(index + 3),
+                        read_u16(index + 5),
+                        read_u16(index + 7),
+                    ];
+                    assert_eq!(gif[index + 10], GIF_MIN_CODE_SIZE as u8);
+                    let (data, next) = read_blocks(index + 11);
+                    frames.push(GifFrame {
+                        delay,
+                        rectangle,
+                        pixels: lzw_decompress(&data),
+                    });
+                    index = next;
+                }
+                0x3B => return frames,
+                byte => panic!("Unexpected block {:#04X}", byte),
+            }
+        }
+    }
+
+    #[test]
+    pub fn gif_recorder_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Draws the 0 of the font at the top left corner, then at 8,8
+        c.load_rom(&[0xF0, 0x29, 0xD0, 0x05, 0x60, 0x08, 0xD0, 0x05])
+            .unwrap();
+        let mut recorder: GifRecorder = GifRecorder::new(&Palette::default(), 1);
+        c.run_frame(2).unwrap();
+        for _ in 0..6 {
+            recorder.capture(&c);
+        }
+        assert_eq!(recorder.frame_count(), 1);
+        c.run_frame(2).unwrap();
+        recorder.capture(&c);
+        assert_eq!(recorder.frame_count(), 2);
+        let gif: Vec<u8> = recorder.finish();
+
+        assert_eq!(&gif[0..6], b"GIF89a");
+        assert_eq!(gif[6..10], [128, 0, 64, 0]);
+        assert_eq!(gif[13..19], [0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF]);
+
+        let frames: Vec<GifFrame> = read_gif_frames(&gif);
+        assert_eq!(frames.len(), 2);
+        // The first frame covers the whole picture for a tenth of a second
+        assert_eq!(frames[0].delay, 10);
+        assert_eq!(frames[0].rectangle, [0, 0, 128, 64]);
+        assert_eq!(frames[0].pixels.len(), 128 * 64);
+        assert_eq!(frames[0].pixels[0..10], [1, 1, 1, 1, 1, 1, 1, 1, 0, 0]);
+        // The second one only the new sprite, 4 pixels wide and 5 high twice over
+        assert_eq!(frames[1].delay, GIF_MIN_DELAY as u16);
+        assert_eq!(frames[1].rectangle, [16, 16, 8, 10]);
+        assert_eq!(frames[1].pixels[0..8], [1, 1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(frames[1].pixels[16..24], [1, 1, 0, 0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    pub fn raw_recorder_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Switches to high resolution and lights its top left pixel
+        c.load_rom(&[0x00, 0xFF, 0xA2, 0x08, 0xD0, 0x01, 0x12, 0x06, 0x80])
+            .unwrap();
+        let mut recorder = RawRecorder::new(Vec::new(), &Palette::default(), 1);
+        assert_eq!((recorder.width(), recorder.height()), (128, 64));
+        recorder.capture(&c).unwrap();
+        c.run_frame(3).unwrap();
+        recorder.capture(&c).unwrap();
+        let frames: Vec<u8> = recorder.finish().unwrap();
+        assert_eq!(frames.len(), 2 * 128 * 64 * 4);
+        assert_eq!(frames[0..4], [0x00, 0x00, 0x00, 0xFF]);
+        // The lit pixel is the top left pixel of the second frame, nothing else is
+        let second: &[u8] = &frames[128 * 64 * 4..];
+        assert_eq!(
+            second[0..8],
+            [0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0xFF]
+        );
+        assert_eq!(
+            second
+                .chunks(4)
+                .filter(|pixel| pixel[0..3] == [0xFF, 0xFF, 0xFF])
+                .count(),
+            1
+        );
+    }
+}
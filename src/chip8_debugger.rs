@@ -0,0 +1,855 @@
+use crate::chip8::{AccessKind, Chip8, MemoryAccess, StepOutcome};
+use crate::chip8_error::Chip8Error;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+// Why the debugger paused the machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugStop {
+    // The program counter reached a breakpoint, the instruction under it is not executed yet.
+    Breakpoint {
+        pc: u16,
+    },
+    // A step, step over, step out or run to command is done.
+    StepDone {
+        pc: u16,
+    },
+    // The instruction at pc accessed watched memory, it is already executed.
+    Watchpoint {
+        pc: u16,
+        opcode: u16,
+        accesses: Vec<MemoryAccess>,
+    },
+    // A step reached the end of the frame without executing anything, because the machine
+    // waits for a key, for the next frame or has exited. The timers ticked.
+    NothingExecuted {
+        pc: u16,
+        outcome: StepOutcome,
+    },
+    // The machine raised an error.
+    Error(Chip8Error),
+}
+
+// The accesses a watchpoint fires on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+// Pauses the machine when an instruction reads or writes memory between start and end
+// included, through FX33, FX55, FX65, 5XY2, 5XY3, F002 or the sprite fetches of DXYN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &MemoryAccess) -> bool {
+        let is_watched_kind: bool = matches!(
+            (self.kind, access.kind),
+            (WatchKind::ReadWrite, _)
+                | (WatchKind::Read, AccessKind::Read)
+                | (WatchKind::Write, AccessKind::Write)
+        );
+        is_watched_kind && (self.start as usize..=self.end as usize).contains(&access.addr)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    Paused,
+    Running,
+    // Executes one instruction
+    SingleStep,
+    // Runs until the subroutine called by the 2NNN at return_address - 2 returns
+    StepOver { depth: usize, return_address: u16 },
+    // Runs until the stack has less than depth entries
+    StepOut { depth: usize },
+    RunTo { address: u16 },
+}
+
+// A command of the debugger prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCommand {
+    Continue,
+    Pause,
+    Step,
+    StepOver,
+    StepOut,
+    RunTo(u16),
+    AddBreakpoint(u16),
+    RemoveBreakpoint(u16),
+    AddWatchpoint(Watchpoint),
+    // Removes the watchpoints starting at the address
+    RemoveWatchpoint(u16),
+    ListBreakpoints,
+    Dump,
+    Help,
+}
+
+pub const DEBUG_HELP: &str = "\
+c            continue
+p            pause
+s            step one instruction
+n            step over a 2NNN call
+o            step out of the current subroutine
+u ADDR       run until the program counter reaches ADDR
+b ADDR       add a breakpoint at ADDR
+d ADDR       delete the breakpoint at ADDR
+w START [END] [r|w|rw]
+             pause after an instruction reads or writes memory from START to END,
+             on reads and writes unless told otherwise
+x START      delete the watchpoints starting at START
+l            list breakpoints and watchpoints
+r            dump registers, stack and timers
+h            show this help
+Addresses are hexadecimal, with or without 0x.";
+
+impl DebugCommand {
+    // Parses a line typed at the debugger prompt, see DEBUG_HELP.
+    pub fn parse(line: &str) -> Result<DebugCommand, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command: &str = words.first().copied().unwrap_or("");
+        let address = || -> Result<u16, String> {
+            match words.get(1) {
+                Some(word) => parse_address(word),
+                None => Err(format!("{} requires an address", command)),
+            }
+        };
+
+        match command {
+            "c" | "continue" => Ok(DebugCommand::Continue),
+            "p" | "pause" => Ok(DebugCommand::Pause),
+            "s" | "step" => Ok(DebugCommand::Step),
+            "n" | "next" => Ok(DebugCommand::StepOver),
+            "o" | "out" => Ok(DebugCommand::StepOut),
+            "u" | "until" => Ok(DebugCommand::RunTo(address()?)),
+            "b" | "break" => Ok(DebugCommand::AddBreakpoint(address()?)),
+            "d" | "delete" => Ok(DebugCommand::RemoveBreakpoint(address()?)),
+            "w" | "watch" => {
+                let start: u16 = address()?;
+                let mut watchpoint = Watchpoint {
+                    start,
+                    end: start,
+                    kind: WatchKind::ReadWrite,
+                };
+                for word in &words[2..] {
+                    match *word {
+                        "r" => watchpoint.kind = WatchKind::Read,
+                        "w" => watchpoint.kind = WatchKind::Write,
+                        "rw" => watchpoint.kind = WatchKind::ReadWrite,
+                        _ => watchpoint.end = parse_address(word)?,
+                    }
+                }
+                if watchpoint.end < watchpoint.start {
+                    return Err(String::from(
+                        "The end of a watchpoint comes before its start",
+                    ));
+                }
+                Ok(DebugCommand::AddWatchpoint(watchpoint))
+            }
+            "x" | "unwatch" => Ok(DebugCommand::RemoveWatchpoint(address()?)),
+            "l" | "list" => Ok(DebugCommand::ListBreakpoints),
+            "r" | "registers" => Ok(DebugCommand::Dump),
+            "h" | "help" => Ok(DebugCommand::Help),
+            _ => Err(format!("Unknown command {}, type h for help", command)),
+        }
+    }
+}
+
+// Hexadecimal, with or without 0x
+fn parse_address(word: &str) -> Result<u16, String> {
+    let digits: &str = word.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid address {}", word))
+}
+
+// The instruction at address, if it is inside memory
+fn opcode_at(chip8: &Chip8, address: u16) -> Option<u16> {
+    let memory: &[u8] = chip8.memory();
+    let address: usize = address as usize;
+    match (memory.get(address), memory.get(address + 1)) {
+        (Some(high), Some(low)) => Some((*high as u16) << 8 | *low as u16),
+        _ => None,
+    }
+}
+
+// Drives a Chip8 in place of Chip8::run_frame, pausing it on breakpoints and stepping
+// through the program on demand.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    mode: RunMode,
+    // Resuming from a breakpoint must execute the instruction under it instead of
+    // stopping right away
+    ignored_breakpoint: Option<u16>,
+    // Cycles run since the timers last ticked, a frame may be split across several
+    // calls to run_frame when the debugger stops in the middle of it
+    frame_cycles: usize,
+    frame_count: u64,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    // Creates a debugger that lets the machine run until a breakpoint is hit.
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            mode: RunMode::Running,
+            ignored_breakpoint: None,
+            frame_cycles: 0,
+            frame_count: 0,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == RunMode::Paused
+    }
+
+    // Frames run to completion, a frame the debugger stopped in the middle of only counts
+    // once a later call to run_frame finishes it.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = RunMode::Paused;
+    }
+
+    pub fn resume(&mut self, chip8: &Chip8) {
+        self.start(RunMode::Running, chip8);
+    }
+
+    // Executes the next instruction, then pauses.
+    pub fn step(&mut self, chip8: &Chip8) {
+        self.start(RunMode::SingleStep, chip8);
+    }
+
+    // Like step, except a 2NNN call runs until the subroutine returns.
+    pub fn step_over(&mut self, chip8: &Chip8) {
+        let pc: u16 = chip8.program_counter();
+        let memory: &[u8] = chip8.memory();
+        let is_call: bool = memory
+            .get(pc as usize)
+            .is_some_and(|byte| byte & 0xF0 == 0x20);
+        if is_call {
+            let mode = RunMode::StepOver {
+                depth: chip8.stack().len(),
+                return_address: pc.wrapping_add(2),
+            };
+            self.start(mode, chip8);
+        } else {
+            self.step(chip8);
+        }
+    }
+
+    // Runs until the current subroutine returns with 00EE.
+    // Outside of a subroutine, this is the same as resume.
+    pub fn step_out(&mut self, chip8: &Chip8) {
+        let mode = RunMode::StepOut {
+            depth: chip8.stack().len(),
+        };
+        self.start(mode, chip8);
+    }
+
+    // Runs until the program counter reaches address.
+    pub fn run_to(&mut self, address: u16, chip8: &Chip8) {
+        self.start(RunMode::RunTo { address }, chip8);
+    }
+
+    fn start(&mut self, mode: RunMode, chip8: &Chip8) {
+        self.mode = mode;
+        self.ignored_breakpoint = Some(chip8.program_counter());
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    // Adds a breakpoint at address, or removes it when there already is one.
+    // Returns true if the breakpoint is now set.
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        if self.remove_breakpoint(address) {
+            return false;
+        }
+        self.add_breakpoint(address);
+        true
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &u16> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    // Removes every watchpoint starting at start, returns false if there was none.
+    pub fn remove_watchpoint(&mut self, start: u16) -> bool {
+        let count: usize = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.start != start);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // Runs one frame like Chip8::run_frame, unless the debugger is paused.
+    // Returns why the machine got paused during the frame. The timers tick once every
+    // cycles_per_frame cycles, so they keep running while stepping through the program.
+    pub fn run_frame(&mut self, chip8: &mut Chip8, cycles_per_frame: usize) -> Option<DebugStop> {
+        if self.is_paused() {
+            return None;
+        }
+
+        // Tracing slows the core down, only pay for it when watching memory
+        let is_watching: bool = !self.watchpoints.is_empty();
+        chip8.set_memory_tracing(is_watching);
+
+        let mut has_executed: bool = false;
+        loop {
+            // Without any cycle per frame, nothing runs and the timers tick, like
+            // Chip8::run_frame
+            if self.frame_cycles >= cycles_per_frame {
+                self.frame_cycles = 0;
+                self.frame_count += 1;
+                chip8.decrement_timers();
+                return None;
+            }
+
+            let pc: u16 = chip8.program_counter();
+            if self.breakpoints.contains(&pc) && self.ignored_breakpoint != Some(pc) {
+                self.pause();
+                return Some(DebugStop::Breakpoint { pc });
+            }
+            self.ignored_breakpoint = None;
+
+            // The instruction may overwrite itself, read it before executing it
+            let opcode: Option<u16> = if is_watching {
+                opcode_at(chip8, pc)
+            } else {
+                None
+            };
+
+            let result: Result<StepOutcome, Chip8Error> = chip8.fetch_cycle();
+            self.frame_cycles += 1;
+            let is_frame_done: bool = self.frame_cycles >= cycles_per_frame;
+            if is_frame_done {
+                self.frame_cycles = 0;
+                self.frame_count += 1;
+                chip8.decrement_timers();
+            }
+
+            let outcome: StepOutcome = match result {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    self.pause();
+                    return Some(DebugStop::Error(e));
+                }
+            };
+
+            if is_watching {
+                let accesses: Vec<MemoryAccess> = chip8
+                    .memory_accesses()
+                    .iter()
+                    .filter(|access| self.watchpoints.iter().any(|w| w.matches(access)))
+                    .copied()
+                    .collect();
+                if !accesses.is_empty() {
+                    self.pause();
+                    return Some(DebugStop::Watchpoint {
+                        pc,
+                        opcode: opcode.unwrap_or(0),
+                        accesses,
+                    });
+                }
+            }
+
+            if outcome == StepOutcome::Executed {
+                has_executed = true;
+                if self.is_step_done(chip8) {
+                    self.pause();
+                    return Some(DebugStop::StepDone {
+                        pc: chip8.program_counter(),
+                    });
+                }
+            }
+
+            if is_frame_done {
+                // Stepping again right away would not get any further until the next frame
+                if self.mode == RunMode::SingleStep && !has_executed {
+                    self.pause();
+                    return Some(DebugStop::NothingExecuted { pc, outcome });
+                }
+                return None;
+            }
+        }
+    }
+
+    fn is_step_done(&self, chip8: &Chip8) -> bool {
+        match self.mode {
+            RunMode::Paused | RunMode::Running => false,
+            RunMode::SingleStep => true,
+            RunMode::StepOver {
+                depth,
+                return_address,
+            } => chip8.stack().len() <= depth && chip8.program_counter() == return_address,
+            RunMode::StepOut { depth } => chip8.stack().len() < depth,
+            RunMode::RunTo { address } => chip8.program_counter() == address,
+        }
+    }
+
+    // Applies a command of the prompt and returns the text to show to the user.
+    pub fn execute(&mut self, command: DebugCommand, chip8: &Chip8) -> String {
+        match command {
+            DebugCommand::Continue => {
+                self.resume(chip8);
+                String::from("Running")
+            }
+            DebugCommand::Pause => {
+                self.pause();
+                Debugger::dump(chip8)
+            }
+            DebugCommand::Step => {
+                self.step(chip8);
+                String::new()
+            }
+            DebugCommand::StepOver => {
+                self.step_over(chip8);
+                String::new()
+            }
+            DebugCommand::StepOut => {
+                self.step_out(chip8);
+                String::new()
+            }
+            DebugCommand::RunTo(address) => {
+                self.run_to(address, chip8);
+                format!("Running until {:#05X}", address)
+            }
+            DebugCommand::AddBreakpoint(address) => {
+                self.add_breakpoint(address);
+                format!("Breakpoint added at {:#05X}", address)
+            }
+            DebugCommand::RemoveBreakpoint(address) => {
+                if self.remove_breakpoint(address) {
+                    format!("Breakpoint removed at {:#05X}", address)
+                } else {
+                    format!("No breakpoint at {:#05X}", address)
+                }
+            }
+            DebugCommand::AddWatchpoint(watchpoint) => {
+                self.add_watchpoint(watchpoint);
+                format!(
+                    "Watchpoint added on {:#05X}-{:#05X}",
+                    watchpoint.start, watchpoint.end
+                )
+            }
+            DebugCommand::RemoveWatchpoint(start) => {
+                if self.remove_watchpoint(start) {
+                    format!("Watchpoint removed at {:#05X}", start)
+                } else {
+                    format!("No watchpoint at {:#05X}", start)
+                }
+            }
+            DebugCommand::ListBreakpoints => {
+                let mut lines: Vec<String> = self
+                    .breakpoints()
+                    .map(|address| format!("Breakpoint {:#05X}", address))
+                    .collect();
+                for watchpoint in self.watchpoints() {
+                    lines.push(format!(
+                        "Watchpoint {:#05X}-{:#05X} {:?}",
+                        watchpoint.start, watchpoint.end, watchpoint.kind
+                    ));
+                }
+                if lines.is_empty() {
+                    String::from("No breakpoints")
+                } else {
+                    lines.join("\n")
+                }
+            }
+            DebugCommand::Dump => Debugger::dump(chip8),
+            DebugCommand::Help => String::from(DEBUG_HELP),
+        }
+    }
+
+    // Describes the message to show when the machine got paused.
+    pub fn describe_stop(stop: &DebugStop, chip8: &Chip8) -> String {
+        match stop {
+            DebugStop::Breakpoint { pc } => {
+                format!("Breakpoint at {:#05X}\n{}", pc, Debugger::dump(chip8))
+            }
+            DebugStop::StepDone { .. } => Debugger::dump(chip8),
+            DebugStop::NothingExecuted { outcome, .. } => {
+                let reason: &str = match outcome {
+                    StepOutcome::WaitingForKey => "Waiting for a key press",
+                    StepOutcome::WaitingForVBlank => "Waiting for the next frame",
+                    StepOutcome::Exited => "The program has exited",
+                    StepOutcome::Executed => "Nothing executed",
+                };
+                format!("{}\n{}", reason, Debugger::dump(chip8))
+            }
+            DebugStop::Watchpoint {
+                pc,
+                opcode,
+                accesses,
+            } => {
+                let mut description: String =
+                    format!("Watchpoint hit by {:#05X} ({:04X})\n", pc, opcode);
+                for access in accesses {
+                    let _ = match access.kind {
+                        AccessKind::Read => writeln!(
+                            description,
+                            "  read  {:#05X}: {:02X}",
+                            access.addr, access.old_value
+                        ),
+                        AccessKind::Write => writeln!(
+                            description,
+                            "  write {:#05X}: {:02X} -> {:02X}",
+                            access.addr, access.old_value, access.new_value
+                        ),
+                    };
+                }
+                description + &Debugger::dump(chip8)
+            }
+            DebugStop::Error(e) => format!("{}\n{}", e, Debugger::dump(chip8)),
+        }
+    }
+
+    // The registers, stack, timers and next instruction of the machine.
+    pub fn dump(chip8: &Chip8) -> String {
+        let mut dump = String::new();
+        let pc: u16 = chip8.program_counter();
+
+        let _ = write!(dump, "PC: {:#05X}", pc);
+        if let Some(opcode) = opcode_at(chip8, pc) {
+            let _ = write!(dump, " ({:04X})", opcode);
+        }
+        let _ = writeln!(
+            dump,
+            "  I: {:#05X}  DT: {}  ST: {}",
+            chip8.index_register(),
+            chip8.delay_timer(),
+            chip8.sound_timer()
+        );
+
+        for (index, value) in chip8.registers().iter().enumerate() {
+            let separator: &str = if index % 8 == 7 { "\n" } else { " " };
+            let _ = write!(dump, "V{:X}: {:02X}{}", index, value, separator);
+        }
+
+        let stack: Vec<String> = chip8
+            .stack()
+            .iter()
+            .map(|address| format!("{:#05X}", address))
+            .collect();
+        let _ = write!(dump, "Stack: [{}]", stack.join(", "));
+        dump
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    // 0x200: call 0x206, then loop on 0x202
+    // 0x206: two instructions, then return
+    fn machine_with_subroutine() -> Chip8 {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[
+            0x22, 0x06, 0x60, 0x01, 0x12, 0x02, 0x61, 0x02, 0x62, 0x03, 0x00, 0xEE,
+        ])
+        .unwrap();
+        c
+    }
+
+    #[test]
+    pub fn breakpoint_test() {
+        let mut c: Chip8 = machine_with_subroutine();
+        let mut d: Debugger = Debugger::new();
+        d.add_breakpoint(0x208);
+
+        assert_eq!(
+            d.run_frame(&mut c, 10),
+            Some(DebugStop::Breakpoint { pc: 0x208 })
+        );
+        assert!(d.is_paused());
+        assert_eq!(c.registers()[1], 2);
+        assert_eq!(c.registers()[2], 0);
+
+        assert_eq!(d.run_frame(&mut c, 10), None);
+        assert_eq!(c.program_counter(), 0x208);
+        assert_eq!(d.frame_count(), 0);
+
+        d.resume(&c);
+        assert_eq!(d.run_frame(&mut c, 10), None);
+        assert_eq!(c.registers()[2], 3);
+        assert_eq!(d.frame_count(), 1);
+    }
+
+    #[test]
+    pub fn step_test() {
+        let mut c: Chip8 = machine_with_subroutine();
+        let mut d: Debugger = Debugger::new();
+        d.pause();
+
+        d.step(&c);
+        assert_eq!(
+            d.run_frame(&mut c, 10),
+            Some(DebugStop::StepDone { pc: 0x206 })
+        );
+        d.step(&c);
+        assert_eq!(
+            d.run_frame(&mut c, 10),
+            Some(DebugStop::StepDone { pc: 0x208 })
+        );
+    }
+
+    #[test]
+    pub fn step_over_test() {
+        let mut c: Chip8 = machine_with_subroutine();
+        let mut d: Debugger = Debugger::new();
+        d.pause();
+
+        d.step_over(&c);
+        assert_eq!(
+            d.run_frame(&mut c, 10),
+            Some(DebugStop::StepDone { pc: 0x202 })
+        );
+        assert_eq!(c.registers()[2], 3);
+
+        d.step_over(&c);
+        assert_eq!(
+            d.run_frame(&mut c, 10),
+            Some(DebugStop::StepDone { pc: 0x204 })
+        );
+    }
+
+    #[test]
+    pub fn step_out_test() {
+        let mut c: Chip8 = machine_with_subroutine();
+        let mut d: Debugger = Debugger::new();
+        d.pause();
+        d.step(&c);
+        d.run_frame(&mut c, 10);
+
+        d.step_out(&c);
+        assert_eq!(
+            d.run_frame(&mut c, 10),
+            Some(DebugStop::StepDone { pc: 0x202 })
+        );
+        assert!(c.stack().is_empty());
+    }
+
+    #[test]
+    pub fn run_to_test() {
+        let mut c: Chip8 = machine_with_subroutine();
+        let mut d: Debugger = Debugger::new();
+        d.run_to(0x20A, &c);
+        assert_eq!(
+            d.run_frame(&mut c, 10),
+            Some(DebugStop::StepDone { pc: 0x20A })
+        );
+    }
+
+    #[test]
+    pub fn error_pauses_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0x00, 0xEE]).unwrap();
+        let mut d: Debugger = Debugger::new();
+        assert_eq!(
+            d.run_frame(&mut c, 10),
+            Some(DebugStop::Error(Chip8Error::StackUnderflow))
+        );
+        assert!(d.is_paused());
+    }
+
+    #[test]
+    pub fn watchpoint_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Sets I to 0x300, then V0 = 123, then stores the BCD of V0 at I, then reads V0 back
+        c.load_rom(&[0xA3, 0x00, 0x60, 0x7B, 0xF0, 0x33, 0xF0, 0x65])
+            .unwrap();
+        let mut d: Debugger = Debugger::new();
+        d.add_watchpoint(Watchpoint {
+            start: 0x301,
+            end: 0x302,
+            kind: WatchKind::Write,
+        });
+
+        assert_eq!(
+            d.run_frame(&mut c, 10),
+            Some(DebugStop::Watchpoint {
+                pc: 0x204,
+                opcode: 0xF033,
+                accesses: vec![
+                    MemoryAccess {
+                        addr: 0x301,
+                        kind: AccessKind::Write,
+                        old_value: 0,
+                        new_value: 2,
+                    },
+                    MemoryAccess {
+                        addr: 0x302,
+                        kind: AccessKind::Write,
+                        old_value: 0,
+                        new_value: 3,
+                    },
+                ],
+            })
+        );
+        assert!(d.is_paused());
+        assert_eq!(c.program_counter(), 0x206);
+
+        // Reads are not watched, the fourth cycle ends the frame
+        d.resume(&c);
+        assert_eq!(d.run_frame(&mut c, 4), None);
+        assert_eq!(c.program_counter(), 0x208);
+    }
+
+    #[test]
+    pub fn sprite_fetch_watchpoint_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0xA3, 0x00, 0xD0, 0x03]).unwrap();
+        let mut d: Debugger = Debugger::new();
+        d.add_watchpoint(Watchpoint {
+            start: 0x302,
+            end: 0x302,
+            kind: WatchKind::Read,
+        });
+
+        match d.run_frame(&mut c, 10) {
+            Some(DebugStop::Watchpoint { pc, accesses, .. }) => {
+                assert_eq!(pc, 0x202);
+                assert_eq!(accesses.len(), 1);
+                assert_eq!(accesses[0].addr, 0x302);
+            }
+            stop => panic!("Unexpected stop {:?}", stop),
+        }
+    }
+
+    #[test]
+    pub fn step_ticks_timers_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Sets DT to 2, then loops until DT reads 0
+        c.load_rom(&[
+            0x60, 0x02, 0xF0, 0x15, 0xF0, 0x07, 0x30, 0x00, 0x12, 0x04, 0x61, 0x01,
+        ])
+        .unwrap();
+        let mut d: Debugger = Debugger::new();
+        d.pause();
+
+        let mut steps: usize = 0;
+        while c.program_counter() != 0x20A {
+            d.step(&c);
+            assert!(matches!(
+                d.run_frame(&mut c, 4),
+                Some(DebugStop::StepDone { .. })
+            ));
+            steps += 1;
+            assert!(steps < 100, "The delay timer never ran out");
+        }
+        assert_eq!(c.delay_timer(), 0);
+    }
+
+    #[test]
+    pub fn no_cycles_test() {
+        let mut c: Chip8 = machine_with_subroutine();
+        let mut d: Debugger = Debugger::new();
+        assert_eq!(d.run_frame(&mut c, 0), None);
+        assert_eq!(c.program_counter(), 0x200);
+        assert_eq!(d.frame_count(), 1);
+    }
+
+    #[test]
+    pub fn step_while_waiting_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::cosmac_vip());
+        c.load_rom(&[0xD0, 0x01, 0x60, 0x01, 0xF1, 0x0A]).unwrap();
+        let mut d: Debugger = Debugger::new();
+        d.pause();
+
+        d.step(&c);
+        assert_eq!(
+            d.run_frame(&mut c, 3),
+            Some(DebugStop::StepDone { pc: 0x202 })
+        );
+        // The display wait holds 6001 until the frame is over
+        d.step(&c);
+        assert_eq!(
+            d.run_frame(&mut c, 3),
+            Some(DebugStop::NothingExecuted {
+                pc: 0x202,
+                outcome: StepOutcome::WaitingForVBlank
+            })
+        );
+        d.step(&c);
+        assert_eq!(
+            d.run_frame(&mut c, 3),
+            Some(DebugStop::StepDone { pc: 0x204 })
+        );
+        assert_eq!(c.registers()[0], 1);
+
+        d.step(&c);
+        assert_eq!(
+            d.run_frame(&mut c, 3),
+            Some(DebugStop::StepDone { pc: 0x206 })
+        );
+        d.step(&c);
+        assert_eq!(
+            d.run_frame(&mut c, 3),
+            Some(DebugStop::NothingExecuted {
+                pc: 0x206,
+                outcome: StepOutcome::WaitingForKey
+            })
+        );
+    }
+
+    #[test]
+    pub fn parse_command_test() {
+        assert_eq!(DebugCommand::parse("c"), Ok(DebugCommand::Continue));
+        assert_eq!(
+            DebugCommand::parse("b 0x2A0"),
+            Ok(DebugCommand::AddBreakpoint(0x2A0))
+        );
+        assert_eq!(
+            DebugCommand::parse("until 3fe"),
+            Ok(DebugCommand::RunTo(0x3FE))
+        );
+        assert!(DebugCommand::parse("b").is_err());
+        assert!(DebugCommand::parse("b zz").is_err());
+        assert!(DebugCommand::parse("jump").is_err());
+        assert_eq!(
+            DebugCommand::parse("w 300 30F w"),
+            Ok(DebugCommand::AddWatchpoint(Watchpoint {
+                start: 0x300,
+                end: 0x30F,
+                kind: WatchKind::Write,
+            }))
+        );
+        assert!(DebugCommand::parse("w 300 2FF").is_err());
+    }
+
+    #[test]
+    pub fn dump_test() {
+        let c: Chip8 = machine_with_subroutine();
+        let dump: String = Debugger::dump(&c);
+        assert!(dump.starts_with("PC: 0x200 (2206)  I: 0x000  DT: 0  ST: 0\n"));
+        assert!(dump.ends_with("Stack: []"));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::chip8::PROGRAM_START;
+use crate::chip8_instruction::Instruction;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// The opcode at address in a ROM loaded at PROGRAM_START
+fn opcode_at(rom: &[u8], address: usize) -> Option<u16> {
+    let offset: usize = address.checked_sub(PROGRAM_START)?;
+    match (rom.get(offset), rom.get(offset + 1)) {
+        (Some(high), Some(low)) => Some((*high as u16) << 8 | *low as u16),
+        _ => None,
+    }
+}
+
+// Size of the instruction at address, so skips can jump over F000 NNNN
+fn instruction_size_at(rom: &[u8], address: usize) -> usize {
+    opcode_at(rom, address)
+        .and_then(Instruction::decode)
+        .map_or(2, |instruction| instruction.size())
+}
+
+// Follows every path the program can take from PROGRAM_START and returns the instructions
+// found on the way, by address. The bytes left out are sprites and other data.
+// BNNN jumps depend on V0, the paths starting from them are not followed.
+pub fn trace_code(rom: &[u8]) -> BTreeMap<usize, Instruction> {
+    let mut code: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut pending: Vec<usize> = vec![PROGRAM_START];
+
+    while let Some(address) = pending.pop() {
+        if code.contains_key(&address) {
+            continue;
+        }
+        let instruction: Instruction = match opcode_at(rom, address).and_then(Instruction::decode) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        code.insert(address, instruction);
+
+        let next: usize = address + instruction.size();
+        match instruction {
+            Instruction::Jump { address } => pending.push(address as usize),
+            Instruction::Call { address } => {
+                pending.push(next);
+                pending.push(address as usize);
+            }
+            Instruction::Return | Instruction::Exit | Instruction::JumpPlusV0 { .. } => {}
+            Instruction::SkipIfEqualByte { .. }
+            | Instruction::SkipIfNotEqualByte { .. }
+            | Instruction::SkipIfEqual { .. }
+            | Instruction::SkipIfNotEqual { .. }
+            | Instruction::SkipIfKeyDown { .. }
+            | Instruction::SkipIfKeyUp { .. } => {
+                pending.push(next);
+                pending.push(next + instruction_size_at(rom, next));
+            }
+            _ => pending.push(next),
+        }
+    }
+    code
+}
+
+fn label(address: u16) -> String {
+    format!("L{:03X}", address)
+}
+
+// Addresses the listing has a line for, instructions are listed whole and the other bytes
+// one by one
+fn line_addresses(rom: &[u8], code: &BTreeMap<usize, Instruction>) -> BTreeSet<usize> {
+    let mut addresses: BTreeSet<usize> = BTreeSet::new();
+    let end: usize = PROGRAM_START + rom.len();
+    let mut address: usize = PROGRAM_START;
+    while address < end {
+        addresses.insert(address);
+        address += code
+            .get(&address)
+            .map_or(1, |instruction| instruction.size());
+    }
+    addresses
+}
+
+// Disassembles a ROM loaded at PROGRAM_START, one line per instruction:
+// ADDR: OPCODE  MNEMONIC operands
+// Jump and call targets get a label, bytes that are not code are listed one by one with
+// their bits drawn as a sprite row.
+// Targets outside of the ROM or in the middle of another instruction have no line to put
+// a label on, they are written as addresses.
+pub fn disassemble(rom: &[u8]) -> String {
+    let code: BTreeMap<usize, Instruction> = trace_code(rom);
+    let lines: BTreeSet<usize> = line_addresses(rom, &code);
+    let labels: BTreeSet<u16> = code
+        .values()
+        .filter_map(|instruction| match instruction {
+            Instruction::Jump { address } | Instruction::Call { address } => Some(*address),
+            _ => None,
+        })
+        .filter(|address| lines.contains(&(*address as usize)))
+        .collect();
+    let target = |address: u16| -> String {
+        if labels.contains(&address) {
+            label(address)
+        } else {
+            format!("{:#05X}", address)
+        }
+    };
+
+    let mut listing = String::new();
+    let end: usize = PROGRAM_START + rom.len();
+    let mut address: usize = PROGRAM_START;
+    while address < end {
+        if labels.contains(&(address as u16)) {
+            let _ = writeln!(listing, "{}:", label(address as u16));
+        }
+
+        let opcode: u16 = opcode_at(rom, address).unwrap_or(0);
+        match code.get(&address) {
+            Some(instruction) => {
+                let (opcode_column, mnemonic): (String, String) = match instruction {
+                    Instruction::Jump { address } => (
+                        format!("{:04X}", opcode),
+                        format!("JP {}", target(*address)),
+                    ),
+                    Instruction::Call { address } => (
+                        format!("{:04X}", opcode),
+                        format!("CALL {}", target(*address)),
+                    ),
+                    Instruction::LoadIndexLong => {
+                        let long_address: u16 = opcode_at(rom, address + 2).unwrap_or(0);
+                        (
+                            format!("{:04X} {:04X}", opcode, long_address),
+                            format!("LD I, {:#06X}", long_address),
+                        )
+                    }
+                    _ => (format!("{:04X}", opcode), instruction.to_string()),
+                };
+                let _ = writeln!(
+                    listing,
+                    "{:03X}: {:<9}  {}",
+                    address, opcode_column, mnemonic
+                );
+                address += instruction.size();
+            }
+            None => {
+                let byte: u8 = rom[address - PROGRAM_START];
+                let bits: String = (0..8)
+                    .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                    .collect();
+                let _ = writeln!(
+                    listing,
+                    "{:03X}: {:<9}  DB {:#04X}  ; {}",
+                    address,
+                    format!("{:02X}", byte),
+                    byte,
+                    bits
+                );
+                address += 1;
+            }
+        }
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn trace_code_test() {
+        // 200: call 206, 202: loop on itself, 204: sprite, 206: skip over F000 NNNN, return
+        let rom: [u8; 14] = [
+            0x22, 0x06, 0x12, 0x02, 0xF0, 0x90, 0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xEE,
+        ];
+        let code: BTreeMap<usize, Instruction> = trace_code(&rom);
+        let addresses: Vec<usize> = code.keys().copied().collect();
+        assert_eq!(addresses, [0x200, 0x202, 0x206, 0x208, 0x20C]);
+        assert_eq!(code[&0x208], Instruction::LoadIndexLong);
+    }
+
+    #[test]
+    pub fn disassemble_test() {
+        let rom: [u8; 8] = [0x22, 0x06, 0x12, 0x02, 0xF0, 0x90, 0x60, 0x0A];
+        assert_eq!(
+            disassemble(&rom),
+            "\
+200: 2206       CALL L206
+L202:
+202: 1202       JP L202
+204: F0         DB 0xF0  ; ####....
+205: 90         DB 0x90  ; #..#....
+L206:
+206: 600A       LD V0, 0x0A
+"
+        );
+    }
+
+    #[test]
+    pub fn unlabelled_target_test() {
+        // 202 jumps into the middle of 204, which calls past the end of the ROM
+        let rom: [u8; 8] = [0x22, 0x04, 0x12, 0x05, 0x24, 0x00, 0x00, 0xEE];
+        assert_eq!(
+            disassemble(&rom),
+            "\
+200: 2204       CALL L204
+202: 1205       JP 0x205
+L204:
+204: 2400       CALL 0x400
+206: 00EE       RET
+"
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use std::error::Error;
+use std::fmt;
+
+// Everything that can go wrong while the emulator core executes a program.
+// The machine state is left as it was when the error happened, so a frontend can display
+// it, hand it to a debugger or keep running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    // The opcode at address pc does not match any instruction.
+    UnknownOpcode { pc: u16, opcode: u16 },
+    // 00EE was executed while no subroutine was being called.
+    StackUnderflow,
+    // 2NNN was executed while the stack was already full.
+    StackOverflow,
+    // An instruction tried to read or write memory past the end of the address space.
+    MemoryOutOfBounds { addr: usize },
+    // An argument does not fit in the bits it is supposed to be encoded in.
+    ArgumentOutOfRange { value: u32, mask: u32 },
+    // The data given to load_state is not a save state this version can restore.
+    InvalidSaveState { reason: &'static str },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "Unknown opcode {:#06X} at address {:#05X}", opcode, pc)
+            }
+            Chip8Error::StackUnderflow => {
+                write!(f, "Returned from a subroutine with an empty stack")
+            }
+            Chip8Error::StackOverflow => write!(f, "Called a subroutine with a full stack"),
+            Chip8Error::MemoryOutOfBounds { addr } => {
+                write!(f, "Memory access outside of memory at address {:#X}", addr)
+            }
+            Chip8Error::ArgumentOutOfRange { value, mask } => {
+                write!(f, "Argument {:#X} is outside of mask {:#X}", value, mask)
+            }
+            Chip8Error::InvalidSaveState { reason } => write!(f, "Invalid save state: {}", reason),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
@@ -0,0 +1,651 @@
+use rusty_chip::chip8::{Chip8, DEFAULT_CYCLES_PER_FRAME, KEY_COUNT};
+use rusty_chip::chip8_capture::{GifRecorder, RawRecorder};
+use rusty_chip::chip8_debugger::Debugger;
+use rusty_chip::chip8_error::Chip8Error;
+use rusty_chip::chip8_image::render_framebuffer;
+use rusty_chip::chip8_palette::Palette;
+use rusty_chip::chip8_rom::Rom;
+use rusty_chip::chip8_sound::{SoundSettings, WavRecorder, Waveform, DEFAULT_SAMPLE_RATE};
+use rusty_chip::Quirks;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+// Runs for 10 seconds unless told otherwise
+pub const DEFAULT_FRAMES: u32 = 600;
+
+// Exit statuses of the headless mode, 1 is left for bad arguments and unreadable files
+// The --until condition was met, or every frame was run when there is no condition
+pub const EXIT_SUCCESS: i32 = 0;
+// The program crashed the emulator
+pub const EXIT_EMULATION_ERROR: i32 = 2;
+// The program exited or ran out of frames before meeting the --until condition
+pub const EXIT_CONDITION_NOT_MET: i32 = 3;
+
+// Character of a pixel for each combination of lit XO-CHIP planes
+const PLANE_CHARACTERS: [char; 4] = ['.', '#', '+', '@'];
+
+// Stops the run as soon as it holds, checked after every instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopCondition {
+    // The program exited with 00FD
+    Exit,
+    ProgramCounter(u16),
+    Register { x: usize, value: u8 },
+    // The sound timer is running
+    Sound,
+    // FX0A is waiting for a key
+    KeyWait,
+}
+
+impl StopCondition {
+    // exit, pc=ADDR, vX=NN, sound or keywait, numbers are hexadecimal like in the debugger
+    pub fn parse(text: &str) -> Option<StopCondition> {
+        match text {
+            "exit" => return Some(StopCondition::Exit),
+            "sound" => return Some(StopCondition::Sound),
+            "keywait" => return Some(StopCondition::KeyWait),
+            _ => {}
+        }
+        let (name, value) = text.split_once('=')?;
+        let value: &str = value.trim_start_matches("0x");
+        if name == "pc" {
+            return u16::from_str_radix(value, 16)
+                .ok()
+                .map(StopCondition::ProgramCounter);
+        }
+        let x: usize = usize::from_str_radix(name.strip_prefix('v')?, 16).ok()?;
+        let value: u8 = u8::from_str_radix(value, 16).ok()?;
+        if x >= 16 {
+            return None;
+        }
+        Some(StopCondition::Register { x, value })
+    }
+
+    fn is_met(&self, chip8: &Chip8) -> bool {
+        match *self {
+            StopCondition::Exit => chip8.has_exited(),
+            StopCondition::ProgramCounter(address) => chip8.program_counter() == address,
+            StopCondition::Register { x, value } => chip8.registers()[x] == value,
+            StopCondition::Sound => chip8.is_sound_active(),
+            StopCondition::KeyWait => chip8.is_waiting_for_key(),
+        }
+    }
+}
+
+// Presses or releases a key at the start of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyInput {
+    pub frame: u32,
+    pub key: u8,
+    pub is_pressed: bool,
+}
+
+impl KeyInput {
+    // KEY@FRAME, the key in hexadecimal and the frame in decimal
+    pub fn parse(text: &str, is_pressed: bool) -> Option<KeyInput> {
+        let (key, frame) = text.split_once('@')?;
+        let key: u8 = u8::from_str_radix(key, 16).ok()?;
+        if key >= KEY_COUNT {
+            return None;
+        }
+        Some(KeyInput {
+            frame: frame.parse().ok()?,
+            key,
+            is_pressed,
+        })
+    }
+}
+
+// Writes the display to a PNG file once a frame has run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot {
+    pub frame: u32,
+    pub filename: String,
+}
+
+pub struct Config {
+    pub cartridge_rom_filename: String,
+    pub quirks: Quirks,
+    pub cycles_per_frame: usize,
+    pub xo_chip: bool,
+    // Runs are reproducible, the random number generator is seeded with 0 by default
+    pub seed: u64,
+    // Most frames to run
+    pub frames: u32,
+    pub until: Option<StopCondition>,
+    pub inputs: Vec<KeyInput>,
+    // Prints the display as text once stopped
+    pub ascii: bool,
+    // Writes the display to a PNG file once stopped
+    pub png_filename: Option<String>,
+    pub screenshots: Vec<Screenshot>,
+    // Records every frame to an animated GIF, or as raw RGBA to a file or - for stdout
+    pub gif_filename: Option<String>,
+    pub raw_filename: Option<String>,
+    // Records the buzzer to a WAV file, as the window would play it
+    pub wav_filename: Option<String>,
+    pub sound: SoundSettings,
+    // Colours and size of the pixels in the PNG files and captures
+    pub palette: Palette,
+    pub scale: usize,
+}
+
+impl Config {
+    // rusty-chip headless <rom> [options]
+    pub fn new(args: &[String]) -> Result<Config, &'static str> {
+        if args.len() < 3 {
+            return Err("headless requires a ROM filename");
+        }
+
+        let cartridge_rom_filename = args[2].clone();
+        let mut quirks: Option<Quirks> = None;
+        let mut cycles_per_frame: usize = DEFAULT_CYCLES_PER_FRAME;
+        let mut xo_chip: bool = false;
+        let mut seed: u64 = 0;
+        let mut frames: u32 = DEFAULT_FRAMES;
+        let mut until: Option<StopCondition> = None;
+        let mut inputs: Vec<KeyInput> = Vec::new();
+        let mut ascii: bool = false;
+        let mut png_filename: Option<String> = None;
+        let mut screenshots: Vec<Screenshot> = Vec::new();
+        let mut gif_filename: Option<String> = None;
+        let mut raw_filename: Option<String> = None;
+        let mut wav_filename: Option<String> = None;
+        let mut sound: SoundSettings = SoundSettings::default();
+        let mut palette: Palette = Palette::default();
+        let mut scale: usize = 1;
+
+        let mut options = args[3..].iter();
+        while let Some(option) = options.next() {
+            match option.as_str() {
+                "--quirks" => match options.next().and_then(|name| Quirks::from_name(name)) {
+                    Some(profile) => quirks = Some(profile),
+                    None => {
+                        return Err("--quirks requires one of vip, chip48, schip, xochip or modern")
+                    }
+                },
+                "--cycles-per-frame" => match options.next().and_then(|n| n.parse().ok()) {
+                    Some(cycles) if cycles > 0 => cycles_per_frame = cycles,
+                    _ => return Err("--cycles-per-frame requires a positive number"),
+                },
+                "--xo-chip" => xo_chip = true,
+                "--seed" => match options.next().and_then(|n| n.parse().ok()) {
+                    Some(number) => seed = number,
+                    None => return Err("--seed requires a number"),
+                },
+                "--frames" => match options.next().and_then(|n| n.parse().ok()) {
+                    Some(count) => frames = count,
+                    None => return Err("--frames requires a number"),
+                },
+                "--until" => match options.next().and_then(|c| StopCondition::parse(c)) {
+                    Some(condition) => until = Some(condition),
+                    None => {
+                        return Err(
+                            "--until requires one of exit, pc=ADDR, vX=NN, sound or keywait",
+                        )
+                    }
+                },
+                "--press" | "--release" => {
+                    let is_pressed: bool = option == "--press";
+                    match options.next().and_then(|i| KeyInput::parse(i, is_pressed)) {
+                        Some(input) => inputs.push(input),
+                        None => return Err("--press and --release require KEY@FRAME"),
+                    }
+                }
+                "--ascii" => ascii = true,
+                "--png" => match options.next() {
+                    Some(filename) => png_filename = Some(filename.clone()),
+                    None => return Err("--png requires a filename"),
+                },
+                "--screenshot-at-frame" => {
+                    let frame: Option<u32> = options.next().and_then(|n| n.parse().ok());
+                    match (frame, options.next()) {
+                        (Some(frame), Some(filename)) => screenshots.push(Screenshot {
+                            frame,
+                            filename: filename.clone(),
+                        }),
+                        _ => return Err("--screenshot-at-frame requires a frame and a filename"),
+                    }
+                }
+                "--gif" => match options.next() {
+                    Some(filename) => gif_filename = Some(filename.clone()),
+                    None => return Err("--gif requires a filename"),
+                },
+                "--raw-frames" => match options.next() {
+                    Some(filename) => raw_filename = Some(filename.clone()),
+                    None => return Err("--raw-frames requires a filename or -"),
+                },
+                "--wav" => match options.next() {
+                    Some(filename) => wav_filename = Some(filename.clone()),
+                    None => return Err("--wav requires a filename"),
+                },
+                "--tone" => match options.next().and_then(|n| n.parse::<f32>().ok()) {
+                    Some(hertz) if hertz > 0.0 && hertz < 20000.0 => sound.frequency = hertz,
+                    _ => return Err("--tone requires a frequency in Hz below 20000"),
+                },
+                "--waveform" => match options.next().and_then(|w| Waveform::from_name(w)) {
+                    Some(waveform) => sound.waveform = waveform,
+                    None => {
+                        return Err("--waveform requires one of square, triangle, sine or noise")
+                    }
+                },
+                "--volume" => match options.next().and_then(|n| n.parse::<u8>().ok()) {
+                    Some(percent) if percent <= 100 => sound.volume = percent as f32 / 100.0,
+                    _ => return Err("--volume requires a percentage from 0 to 100"),
+                },
+                "--palette" => match options.next().and_then(|text| Palette::parse(text)) {
+                    Some(colors) => palette = colors,
+                    None => {
+                        return Err("--palette requires grey, green, amber, lcd or #RRGGBB,#RRGGBB")
+                    }
+                },
+                "--scale" => match options.next().and_then(|n| n.parse().ok()) {
+                    Some(factor) if factor > 0 => scale = factor,
+                    _ => return Err("--scale requires a positive number"),
+                },
+                _ => return Err("unknown option"),
+            }
+        }
+
+        // XO-CHIP programs expect the quirks of Octo unless told otherwise
+        let quirks: Quirks = quirks.unwrap_or_else(|| {
+            if xo_chip {
+                Quirks::xo_chip()
+            } else {
+                Quirks::default()
+            }
+        });
+        // Inputs of the same frame are applied in the order they were given
+        inputs.sort_by_key(|input| input.frame);
+        screenshots.sort_by_key(|screenshot| screenshot.frame);
+
+        Ok(Config {
+            cartridge_rom_filename,
+            quirks,
+            cycles_per_frame,
+            xo_chip,
+            seed,
+            frames,
+            until,
+            inputs,
+            ascii,
+            png_filename,
+            screenshots,
+            gif_filename,
+            raw_filename,
+            wav_filename,
+            sound,
+            palette,
+            scale,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    ConditionMet,
+    Exited,
+    // Every frame was run
+    OutOfFrames,
+    Error(Chip8Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub outcome: Outcome,
+    // Frames started, the last one may have been cut short
+    pub frames: u32,
+    // Instructions fetched, including the cycles spent waiting for a key or the display
+    pub cycles: u64,
+}
+
+impl Report {
+    pub fn exit_code(&self, until: Option<StopCondition>) -> i32 {
+        match self.outcome {
+            Outcome::ConditionMet => EXIT_SUCCESS,
+            Outcome::Error(_) => EXIT_EMULATION_ERROR,
+            Outcome::Exited | Outcome::OutOfFrames if until.is_some() => EXIT_CONDITION_NOT_MET,
+            Outcome::Exited | Outcome::OutOfFrames => EXIT_SUCCESS,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let outcome: String = match &self.outcome {
+            Outcome::ConditionMet => String::from("Condition met"),
+            Outcome::Exited => String::from("Program exited"),
+            Outcome::OutOfFrames => String::from("Ran every frame"),
+            Outcome::Error(e) => format!("Emulation stopped: {}", e),
+        };
+        format!(
+            "{} after {} frames and {} cycles",
+            outcome, self.frames, self.cycles
+        )
+    }
+}
+
+// Runs frames the way Chip8::run_frame does, checking the condition after every instruction.
+// on_frame is called with the number of frames run after each complete frame.
+pub fn run_machine(
+    chip8: &mut Chip8,
+    config: &Config,
+    mut on_frame: impl FnMut(u32, &Chip8),
+) -> Report {
+    let mut inputs = config.inputs.iter().peekable();
+    let mut cycles: u64 = 0;
+
+    for frame in 0..config.frames {
+        while let Some(input) = inputs.next_if(|input| input.frame == frame) {
+            let result: Result<(), Chip8Error> = if input.is_pressed {
+                chip8.press_key(input.key)
+            } else {
+                chip8.release_key(input.key)
+            };
+            if let Err(e) = result {
+                return Report {
+                    outcome: Outcome::Error(e),
+                    frames: frame,
+                    cycles,
+                };
+            }
+        }
+
+        for _ in 0..config.cycles_per_frame {
+            let result: Result<_, Chip8Error> = chip8.fetch_cycle();
+            cycles += 1;
+            let outcome: Option<Outcome> = match result {
+                Err(e) => Some(Outcome::Error(e)),
+                Ok(_) if config.until.is_some_and(|until| until.is_met(chip8)) => {
+                    Some(Outcome::ConditionMet)
+                }
+                Ok(_) if chip8.has_exited() => Some(Outcome::Exited),
+                Ok(_) => None,
+            };
+            if let Some(outcome) = outcome {
+                return Report {
+                    outcome,
+                    frames: frame + 1,
+                    cycles,
+                };
+            }
+        }
+        chip8.decrement_timers();
+        on_frame(frame + 1, chip8);
+    }
+
+    Report {
+        outcome: Outcome::OutOfFrames,
+        frames: config.frames,
+        cycles,
+    }
+}
+
+// The display, one character per pixel
+pub fn framebuffer_to_ascii(chip8: &Chip8) -> String {
+    let mut ascii = String::new();
+    for row in chip8.framebuffer().chunks(chip8.display_width()) {
+        ascii.extend(
+            row.iter()
+                .map(|planes| PLANE_CHARACTERS[(planes & 0b11) as usize]),
+        );
+        ascii.push('\n');
+    }
+    ascii
+}
+
+// Returns the exit status of the process
+pub fn run(config: Config) -> Result<i32, Box<dyn Error>> {
+    let cartridge_rom: Rom = Rom::from_path(&config.cartridge_rom_filename, config.xo_chip)
+        .map_err(|e| format!("Unable to load {}: {}", config.cartridge_rom_filename, e))?;
+    if cartridge_rom.is_odd_length() {
+        eprintln!(
+            "{} has an odd length, it may have been cut short",
+            config.cartridge_rom_filename
+        );
+    }
+
+    let mut chip8: Chip8 = if config.xo_chip {
+        Chip8::new_xo_chip(config.quirks)
+    } else {
+        Chip8::new(config.quirks)
+    };
+    chip8.set_seed(config.seed);
+    chip8.load_rom(cartridge_rom.bytes())?;
+
+    let mut gif_recorder: Option<GifRecorder> = config
+        .gif_filename
+        .as_ref()
+        .map(|_| GifRecorder::new(&config.palette, config.scale));
+    // The raw frames take stdout over, everything else is printed to stderr then
+    let is_raw_on_stdout: bool = config.raw_filename.as_deref() == Some("-");
+    let raw_writer: Option<Box<dyn Write>> = match config.raw_filename.as_deref() {
+        None => None,
+        Some("-") => Some(Box::new(BufWriter::new(io::stdout()))),
+        Some(filename) => Some(Box::new(BufWriter::new(
+            File::create(filename).map_err(|e| format!("Unable to write {}: {}", filename, e))?,
+        ))),
+    };
+    let mut raw_recorder: Option<RawRecorder<Box<dyn Write>>> =
+        raw_writer.map(|writer| RawRecorder::new(writer, &config.palette, config.scale));
+    if let Some(recorder) = &raw_recorder {
+        eprintln!(
+            "Raw frames are {}x{} RGBA at 60 frames per second",
+            recorder.width(),
+            recorder.height()
+        );
+    }
+
+    let mut wav_recorder: Option<WavRecorder> = config
+        .wav_filename
+        .as_ref()
+        .map(|_| WavRecorder::new(config.sound, DEFAULT_SAMPLE_RATE));
+
+    let mut screenshots = config.screenshots.iter().peekable();
+    let mut write_error: Option<String> = None;
+    let report: Report = run_machine(&mut chip8, &config, |frame, chip8| {
+        while let Some(screenshot) = screenshots.next_if(|s| s.frame <= frame) {
+            if let Err(e) = write_png(chip8, &config, &screenshot.filename) {
+                write_error.get_or_insert(e);
+            }
+        }
+        if let Some(recorder) = &mut gif_recorder {
+            recorder.capture(chip8);
+        }
+        if let Some(recorder) = &mut wav_recorder {
+            recorder.capture(chip8);
+        }
+        if let Some(recorder) = &mut raw_recorder {
+            if let Err(e) = recorder.capture(chip8) {
+                write_error.get_or_insert(format!("Unable to write the raw frames: {}", e));
+            }
+        }
+    });
+    if let Some(e) = write_error {
+        return Err(e.into());
+    }
+    if let Some(recorder) = raw_recorder {
+        recorder
+            .finish()
+            .map_err(|e| format!("Unable to write the raw frames: {}", e))?;
+    }
+    if let (Some(recorder), Some(gif_filename)) = (gif_recorder, &config.gif_filename) {
+        let frame_count: usize = recorder.frame_count();
+        fs::write(gif_filename, recorder.finish())
+            .map_err(|e| format!("Unable to write {}: {}", gif_filename, e))?;
+        eprintln!("Wrote {} frames to {}", frame_count, gif_filename);
+    }
+    if let (Some(recorder), Some(wav_filename)) = (wav_recorder, &config.wav_filename) {
+        fs::write(wav_filename, recorder.finish())
+            .map_err(|e| format!("Unable to write {}: {}", wav_filename, e))?;
+    }
+    for screenshot in screenshots {
+        eprintln!(
+            "No screenshot written to {}, the run stopped before frame {}",
+            screenshot.filename, screenshot.frame
+        );
+    }
+    let mut text: String = format!(
+        "ROM: {}\n{}\n{}\n",
+        cartridge_rom.describe(),
+        report.describe(),
+        Debugger::dump(&chip8)
+    );
+    if config.ascii {
+        text.push_str(&framebuffer_to_ascii(&chip8));
+    }
+    if is_raw_on_stdout {
+        eprint!("{}", text);
+    } else {
+        print!("{}", text);
+    }
+    if let Some(png_filename) = &config.png_filename {
+        write_png(&chip8, &config, png_filename)?;
+    }
+    Ok(report.exit_code(config.until))
+}
+
+fn write_png(chip8: &Chip8, config: &Config, filename: &str) -> Result<(), String> {
+    let png: Vec<u8> = render_framebuffer(chip8, &config.palette, config.scale).to_png();
+    fs::write(filename, png).map_err(|e| format!("Unable to write {}: {}", filename, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_config(options: &[&str]) -> Config {
+        let mut args: Vec<String> = vec![String::from("rusty-chip"), String::from("headless")];
+        args.push(String::from("rom.ch8"));
+        args.extend(options.iter().map(|option| option.to_string()));
+        Config::new(&args).unwrap()
+    }
+
+    #[test]
+    pub fn parse_test() {
+        assert_eq!(StopCondition::parse("exit"), Some(StopCondition::Exit));
+        assert_eq!(
+            StopCondition::parse("pc=0x2A0"),
+            Some(StopCondition::ProgramCounter(0x2A0))
+        );
+        assert_eq!(
+            StopCondition::parse("vF=1"),
+            Some(StopCondition::Register { x: 0xF, value: 1 })
+        );
+        assert_eq!(StopCondition::parse("v10=1"), None);
+        assert_eq!(StopCondition::parse("pc"), None);
+        assert_eq!(
+            KeyInput::parse("A@120", true),
+            Some(KeyInput {
+                frame: 120,
+                key: 0xA,
+                is_pressed: true
+            })
+        );
+        assert_eq!(KeyInput::parse("10@1", true), None);
+
+        let config: Config =
+            parse_config(&["--release", "5@20", "--press", "5@10", "--frames", "30"]);
+        assert_eq!(config.frames, 30);
+        assert_eq!(config.inputs[0].frame, 10);
+        assert_eq!(config.inputs[1].frame, 20);
+
+        let config: Config = parse_config(&[
+            "--screenshot-at-frame",
+            "60",
+            "b.png",
+            "--screenshot-at-frame",
+            "30",
+            "a.png",
+            "--scale",
+            "10",
+        ]);
+        assert_eq!(config.screenshots[0].frame, 30);
+        assert_eq!(config.screenshots[1].filename, "b.png");
+        assert_eq!(config.scale, 10);
+
+        let args: Vec<String> = [
+            "rusty-chip",
+            "headless",
+            "rom.ch8",
+            "--cycles-per-frame",
+            "0",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        assert!(Config::new(&args).is_err());
+    }
+
+    #[test]
+    pub fn run_until_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Waits for a key, then stores it in V1 and loops forever
+        c.load_rom(&[0xF1, 0x0A, 0x12, 0x02]).unwrap();
+        let config: Config =
+            parse_config(&["--until", "v1=5", "--press", "5@3", "--release", "5@4"]);
+        let report: Report = run_machine(&mut c, &config, |_, _| {});
+        assert_eq!(report.outcome, Outcome::ConditionMet);
+        assert_eq!(report.frames, 4);
+        assert_eq!(report.cycles, 31);
+        assert_eq!(report.exit_code(config.until), EXIT_SUCCESS);
+
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0xF1, 0x0A, 0x12, 0x02]).unwrap();
+        let config: Config = parse_config(&["--until", "v1=5", "--frames", "10"]);
+        let report: Report = run_machine(&mut c, &config, |_, _| {});
+        assert_eq!(report.outcome, Outcome::OutOfFrames);
+        assert_eq!(report.cycles, 100);
+        assert_eq!(report.exit_code(config.until), EXIT_CONDITION_NOT_MET);
+    }
+
+    #[test]
+    pub fn run_error_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0x00, 0xEE]).unwrap();
+        let report: Report = run_machine(&mut c, &parse_config(&[]), |_, _| {});
+        assert_eq!(report.outcome, Outcome::Error(Chip8Error::StackUnderflow));
+        assert_eq!(report.exit_code(None), EXIT_EMULATION_ERROR);
+    }
+
+    #[test]
+    pub fn beep_length_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Waits for a key, then beeps for 5 frames and loops forever
+        c.load_rom(&[0xF1, 0x0A, 0x60, 0x05, 0xF0, 0x18, 0x12, 0x06])
+            .unwrap();
+        let config: Config = parse_config(&["--frames", "12", "--press", "5@3", "--wav", "a.wav"]);
+        let mut recorder = WavRecorder::new(config.sound, DEFAULT_SAMPLE_RATE);
+        run_machine(&mut c, &config, |_, chip8| recorder.capture(chip8));
+
+        // The key is read on frame 3, which starts the beep
+        let samples: &[f32] = recorder.samples();
+        let frame_length: usize = DEFAULT_SAMPLE_RATE as usize / 60;
+        let is_silent = |frame: usize| {
+            samples[frame * frame_length..(frame + 1) * frame_length]
+                .iter()
+                .all(|sample| *sample == 0.0)
+        };
+        let beeping: Vec<usize> = (0..12).filter(|frame| !is_silent(*frame)).collect();
+        // The fade out spills over into frame 8
+        assert_eq!(beeping, [3, 4, 5, 6, 7, 8]);
+        assert!(samples[3 * frame_length..8 * frame_length]
+            .iter()
+            .all(|sample| *sample != 0.0));
+        assert!(samples[8 * frame_length + frame_length / 2..]
+            .iter()
+            .all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    pub fn framebuffer_to_ascii_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Draws the 1 of the font in the top left corner
+        c.load_rom(&[0x60, 0x01, 0xF0, 0x29, 0xD1, 0x15]).unwrap();
+        c.run_frame(3).unwrap();
+        let ascii: String = framebuffer_to_ascii(&c);
+        assert!(ascii.starts_with(&format!("..#{}\n.##", ".".repeat(61))));
+        assert_eq!(ascii.lines().count(), 32);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::chip8::Chip8;
+use crate::chip8_palette::{Palette, Rgb};
+use crate::chip8_util::crc32;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// Deflate only looks this far back for repeated bytes
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+// RGBA pixels, row after row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl Image {
+    pub fn to_png(&self) -> Vec<u8> {
+        encode_png(self.width, self.height, &self.rgba)
+    }
+}
+
+// Renders the display in the colours of the palette, every pixel as a square of
+// scale by scale image pixels
+pub fn render_framebuffer(chip8: &Chip8, palette: &Palette, scale: usize) -> Image {
+    let width: usize = chip8.display_width() * scale;
+    let height: usize = chip8.display_height() * scale;
+    let mut rgba: Vec<u8> = Vec::with_capacity(width * height * 4);
+    for row in chip8.framebuffer().chunks(chip8.display_width()) {
+        let line: Vec<u8> = row
+            .iter()
+            .flat_map(|planes| {
+                let [red, green, blue]: Rgb = palette.color(*planes);
+                [red, green, blue, 0xFF].repeat(scale)
+            })
+            .collect();
+        for _ in 0..scale {
+            rgba.extend_from_slice(&line);
+        }
+    }
+    Image {
+        width,
+        height,
+        rgba,
+    }
+}
+
+// Encodes RGBA pixels, row after row, as a PNG file.
+// https://www.w3.org/TR/png/
+pub fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), width * height * 4, "not an RGBA image");
+
+    let mut ihdr: Vec<u8> = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, not interlaced
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // Every row starts with its filter type, always none here
+    let mut scanlines: Vec<u8> = Vec::with_capacity(height * (width * 4 + 1));
+    for row in rgba.chunks(width * 4) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut png: Vec<u8> = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_compress(&scanlines, width * 4 + 1));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start: usize = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let checksum: u32 = crc32(&png[start..]);
+    png.extend_from_slice(&checksum.to_be_bytes());
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b): (u32, u32) = (1, 0);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+// Packs bits into bytes least significant bit first, as deflate and GIF streams do
+pub(crate) struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    pub(crate) fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    pub(crate) fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are stored most significant bit first
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write_bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc1951#section-3.2.6
+fn write_symbol(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// Index of the last base not greater than value
+fn base_index(bases: &[u16], value: usize) -> usize {
+    bases
+        .iter()
+        .rposition(|base| *base as usize <= value)
+        .unwrap_or(0)
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let index: usize = base_index(&LENGTH_BASES, length);
+    write_symbol(writer, 257 + index as u32);
+    writer.write_bits(
+        (length - LENGTH_BASES[index] as usize) as u32,
+        LENGTH_EXTRA_BITS[index] as u32,
+    );
+    let index: usize = base_index(&DISTANCE_BASES, distance);
+    writer.write_code(index as u32, 5);
+    writer.write_bits(
+        (distance - DISTANCE_BASES[index] as usize) as u32,
+        DISTANCE_EXTRA_BITS[index] as u32,
+    );
+}
+
+// Compresses with a single block of fixed Huffman codes. Screens are made of runs of identical
+// pixels and of rows repeating the one above, so only those two distances are searched.
+fn zlib_compress(data: &[u8], row_size: usize) -> Vec<u8> {
+    let mut writer = BitWriter {
+        bytes: vec![0x78, 0x01],
+        buffer: 0,
+        count: 0,
+    };
+    // Last block, fixed Huffman codes
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let match_length = |position: usize, distance: usize| -> usize {
+        if distance == 0 || distance > position || distance > WINDOW_SIZE {
+            return 0;
+        }
+        (0..MAX_MATCH.min(data.len() - position))
+            .take_while(|offset| data[position + offset] == data[position + offset - distance])
+            .count()
+    };
+
+    let mut position: usize = 0;
+    while position < data.len() {
+        let (length, distance): (usize, usize) = [4, row_size]
+            .iter()
+            .map(|distance| (match_length(position, *distance), *distance))
+            .max()
+            .unwrap_or((0, 0));
+        if length >= MIN_MATCH {
+            write_match(&mut writer, length, distance);
+            position += length;
+        } else {
+            write_symbol(&mut writer, data[position] as u32);
+            position += 1;
+        }
+    }
+    write_symbol(&mut writer, 256);
+
+    let mut compressed: Vec<u8> = writer.finish();
+    compressed.extend_from_slice(&adler32(data).to_be_bytes());
+    compressed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    // Inflates a stream of fixed Huffman codes, enough to check what zlib_compress writes
+    fn inflate_fixed(compressed: &[u8]) -> Vec<u8> {
+        let mut position: usize = 16;
+        let mut bit = || {
+            let value: u32 = (compressed[position / 8] >> (position % 8)) as u32 & 1;
+            position += 1;
+            value
+        };
+        let read_bits = |bit: &mut dyn FnMut() -> u32, count: u8| -> usize {
+            (0..count).fold(0, |value, index| value | (bit() as usize) << index)
+        };
+        assert_eq!(read_bits(&mut bit, 3), 0b011);
+
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let mut code: u32 = 0;
+            let mut length: u32 = 0;
+            let symbol: u32 = loop {
+                code = code << 1 | bit();
+                length += 1;
+                match (length, code) {
+                    (7, 0..=0x17) => break code + 256,
+                    (8, 0x30..=0xBF) => break code - 0x30,
+                    (8, 0xC0..=0xC7) => break code - 0xC0 + 280,
+                    (9, 0x190..=0x1FF) => break code - 0x190 + 144,
+                    _ => {}
+                }
+            };
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => return out,
+                _ => {
+                    let index: usize = (symbol - 257) as usize;
+                    let length: usize = LENGTH_BASES[index] as usize
+                        + read_bits(&mut bit, LENGTH_EXTRA_BITS[index]);
+                    let index: usize = (0..5).fold(0, |value, _| value << 1 | bit() as usize);
+                    let distance: usize = DISTANCE_BASES[index] as usize
+                        + read_bits(&mut bit, DISTANCE_EXTRA_BITS[index]);
+                    for _ in 0..length {
+                        out.push(out[out.len() - distance]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn zlib_compress_test() {
+        let mut data: Vec<u8> = vec![7; 1000];
+        data.extend((0..=255).collect::<Vec<u8>>());
+        data.extend_from_within(900..1256);
+        let compressed: Vec<u8> = zlib_compress(&data, 356);
+        assert!(compressed.len() < 400);
+        assert_eq!(&compressed[0..2], [0x78, 0x01]);
+        assert_eq!(inflate_fixed(&compressed), data);
+        assert_eq!(
+            compressed[compressed.len() - 4..],
+            adler32(&data).to_be_bytes()
+        );
+    }
+
+    #[test]
+    pub fn encode_png_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Draws the 0 of the font in the top left corner
+        c.load_rom(&[0xF0, 0x29, 0xD0, 0x05]).unwrap();
+        c.run_frame(2).unwrap();
+        let rgba: Vec<u8> = render_framebuffer(&c, &Palette::default(), 1).rgba;
+        assert_eq!(rgba[0..8], [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(rgba[16..20], [0x00, 0x00, 0x00, 0xFF]);
+
+        let png: Vec<u8> = encode_png(64, 32, &rgba);
+        assert_eq!(png[0..8], PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..24], [0, 0, 0, 64, 0, 0, 0, 32]);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+
+        let idat_length: usize = u32::from_be_bytes([png[33], png[34], png[35], png[36]]) as usize;
+        let scanlines: Vec<u8> = inflate_fixed(&png[41..41 + idat_length]);
+        assert_eq!(scanlines.len(), 32 * (64 * 4 + 1));
+        assert_eq!(scanlines[1..257], rgba[0..256]);
+    }
+
+    #[test]
+    pub fn render_framebuffer_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Draws the 0 of the font in the top left corner
+        c.load_rom(&[0xF0, 0x29, 0xD0, 0x05]).unwrap();
+        c.run_frame(2).unwrap();
+        let palette: Palette = Palette::from_name("amber").unwrap();
+        let image: Image = render_framebuffer(&c, &palette, 3);
+        assert_eq!((image.width, image.height), (192, 96));
+        assert_eq!(image.rgba.len(), 192 * 96 * 4);
+
+        let pixel = |x: usize, y: usize| -> &[u8] {
+            let index: usize = (y * image.width + x) * 4;
+            &image.rgba[index..index + 3]
+        };
+        // The top left corner of the 0 is lit, its middle is not
+        assert_eq!(pixel(0, 0), palette.foreground());
+        assert_eq!(pixel(2, 2), palette.foreground());
+        assert_eq!(pixel(3, 3), palette.background());
+        assert_eq!(pixel(4, 4), palette.background());
+        assert_eq!(pixel(3, 2), palette.foreground());
+    }
+}
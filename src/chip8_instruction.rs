@@ -0,0 +1,323 @@
+use std::fmt;
+
+// A decoded opcode, shared by the interpreter and the disassembler.
+// x and y are register numbers, byte and n are immediate values and address is the 12 bit
+// NNN part of the opcode.
+// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#3.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // 0000, used for old machines, does nothing
+    Nop,
+    // 00E0
+    ClearScreen,
+    // 00EE
+    Return,
+    // 00CN
+    ScrollDown { n: u8 },
+    // 00DN
+    ScrollUp { n: u8 },
+    // 00FB
+    ScrollRight,
+    // 00FC
+    ScrollLeft,
+    // 00FD
+    Exit,
+    // 00FE
+    LowResolution,
+    // 00FF
+    HighResolution,
+    // 1NNN
+    Jump { address: u16 },
+    // 2NNN
+    Call { address: u16 },
+    // 3XKK
+    SkipIfEqualByte { x: u8, byte: u8 },
+    // 4XKK
+    SkipIfNotEqualByte { x: u8, byte: u8 },
+    // 5XY0
+    SkipIfEqual { x: u8, y: u8 },
+    // 5XY2
+    StoreRegisterRange { x: u8, y: u8 },
+    // 5XY3
+    ReadRegisterRange { x: u8, y: u8 },
+    // 6XKK
+    LoadByte { x: u8, byte: u8 },
+    // 7XKK
+    AddByte { x: u8, byte: u8 },
+    // 8XY0
+    Load { x: u8, y: u8 },
+    // 8XY1
+    Or { x: u8, y: u8 },
+    // 8XY2
+    And { x: u8, y: u8 },
+    // 8XY3
+    Xor { x: u8, y: u8 },
+    // 8XY4
+    Add { x: u8, y: u8 },
+    // 8XY5
+    Sub { x: u8, y: u8 },
+    // 8XY6
+    ShiftRight { x: u8, y: u8 },
+    // 8XY7
+    SubNot { x: u8, y: u8 },
+    // 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    // 9XY0
+    SkipIfNotEqual { x: u8, y: u8 },
+    // ANNN
+    LoadIndex { address: u16 },
+    // BNNN
+    JumpPlusV0 { address: u16 },
+    // CXKK
+    Random { x: u8, byte: u8 },
+    // DXYN
+    Draw { x: u8, y: u8, n: u8 },
+    // EX9E
+    SkipIfKeyDown { x: u8 },
+    // EXA1
+    SkipIfKeyUp { x: u8 },
+    // F000 NNNN, the address is the word after the instruction
+    LoadIndexLong,
+    // FN01
+    SelectPlanes { n: u8 },
+    // F002
+    LoadAudioPattern,
+    // FX07
+    ReadDelayTimer { x: u8 },
+    // FX0A
+    WaitForKey { x: u8 },
+    // FX15
+    SetDelayTimer { x: u8 },
+    // FX18
+    SetSoundTimer { x: u8 },
+    // FX1E
+    AddIndex { x: u8 },
+    // FX29
+    LoadCharacter { x: u8 },
+    // FX30
+    LoadBigCharacter { x: u8 },
+    // FX33
+    StoreBcd { x: u8 },
+    // FX3A
+    SetPitch { x: u8 },
+    // FX55
+    StoreRegisters { x: u8 },
+    // FX65
+    ReadRegisters { x: u8 },
+    // FX75
+    StoreFlags { x: u8 },
+    // FX85
+    ReadFlags { x: u8 },
+}
+
+impl Instruction {
+    // Returns None for opcodes that no supported interpreter knows.
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        let x: u8 = ((opcode & 0x0F00) >> 8) as u8;
+        let y: u8 = ((opcode & 0x00F0) >> 4) as u8;
+        let n: u8 = (opcode & 0x000F) as u8;
+        let byte: u8 = (opcode & 0x00FF) as u8;
+        let address: u16 = opcode & 0x0FFF;
+
+        let instruction: Instruction = match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x0000 => Instruction::Nop,
+                0x00E0 => Instruction::ClearScreen,
+                0x00EE => Instruction::Return,
+                0x00FB => Instruction::ScrollRight,
+                0x00FC => Instruction::ScrollLeft,
+                0x00FD => Instruction::Exit,
+                0x00FE => Instruction::LowResolution,
+                0x00FF => Instruction::HighResolution,
+                _ if opcode & 0xFFF0 == 0x00C0 => Instruction::ScrollDown { n },
+                _ if opcode & 0xFFF0 == 0x00D0 => Instruction::ScrollUp { n },
+                _ => return None,
+            },
+            0x1000 => Instruction::Jump { address },
+            0x2000 => Instruction::Call { address },
+            0x3000 => Instruction::SkipIfEqualByte { x, byte },
+            0x4000 => Instruction::SkipIfNotEqualByte { x, byte },
+            0x5000 => match n {
+                0x0 => Instruction::SkipIfEqual { x, y },
+                0x2 => Instruction::StoreRegisterRange { x, y },
+                0x3 => Instruction::ReadRegisterRange { x, y },
+                _ => return None,
+            },
+            0x6000 => Instruction::LoadByte { x, byte },
+            0x7000 => Instruction::AddByte { x, byte },
+            0x8000 => match n {
+                0x0 => Instruction::Load { x, y },
+                0x1 => Instruction::Or { x, y },
+                0x2 => Instruction::And { x, y },
+                0x3 => Instruction::Xor { x, y },
+                0x4 => Instruction::Add { x, y },
+                0x5 => Instruction::Sub { x, y },
+                0x6 => Instruction::ShiftRight { x, y },
+                0x7 => Instruction::SubNot { x, y },
+                0xE => Instruction::ShiftLeft { x, y },
+                _ => return None,
+            },
+            0x9000 => Instruction::SkipIfNotEqual { x, y },
+            0xA000 => Instruction::LoadIndex { address },
+            0xB000 => Instruction::JumpPlusV0 { address },
+            0xC000 => Instruction::Random { x, byte },
+            0xD000 => Instruction::Draw { x, y, n },
+            0xE000 => match byte {
+                0x9E => Instruction::SkipIfKeyDown { x },
+                0xA1 => Instruction::SkipIfKeyUp { x },
+                _ => return None,
+            },
+            0xF000 => match byte {
+                0x00 if x == 0 => Instruction::LoadIndexLong,
+                0x01 => Instruction::SelectPlanes { n: x },
+                0x02 if x == 0 => Instruction::LoadAudioPattern,
+                0x07 => Instruction::ReadDelayTimer { x },
+                0x0A => Instruction::WaitForKey { x },
+                0x15 => Instruction::SetDelayTimer { x },
+                0x18 => Instruction::SetSoundTimer { x },
+                0x1E => Instruction::AddIndex { x },
+                0x29 => Instruction::LoadCharacter { x },
+                0x30 => Instruction::LoadBigCharacter { x },
+                0x33 => Instruction::StoreBcd { x },
+                0x3A => Instruction::SetPitch { x },
+                0x55 => Instruction::StoreRegisters { x },
+                0x65 => Instruction::ReadRegisters { x },
+                0x75 => Instruction::StoreFlags { x },
+                0x85 => Instruction::ReadFlags { x },
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    // The instructions only XO-CHIP machines know.
+    pub fn is_xo_chip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::ScrollUp { .. }
+                | Instruction::StoreRegisterRange { .. }
+                | Instruction::ReadRegisterRange { .. }
+                | Instruction::LoadIndexLong
+                | Instruction::SelectPlanes { .. }
+                | Instruction::LoadAudioPattern
+                | Instruction::SetPitch { .. }
+        )
+    }
+
+    // Length in bytes, F000 NNNN is the only instruction spanning two words.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadIndexLong => 4,
+            _ => 2,
+        }
+    }
+}
+
+// Mnemonics of Cowgod's Chip-8 Technical Reference, extended for SUPER-CHIP and XO-CHIP.
+// Immediate values are hexadecimal, except for the N of DXYN, 00CN, 00DN and FN01.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Nop => write!(f, "SYS 0x000"),
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollUp { n } => write!(f, "SCU {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowResolution => write!(f, "LOW"),
+            Instruction::HighResolution => write!(f, "HIGH"),
+            Instruction::Jump { address } => write!(f, "JP {:#05X}", address),
+            Instruction::Call { address } => write!(f, "CALL {:#05X}", address),
+            Instruction::SkipIfEqualByte { x, byte } => write!(f, "SE V{:X}, {:#04X}", x, byte),
+            Instruction::SkipIfNotEqualByte { x, byte } => {
+                write!(f, "SNE V{:X}, {:#04X}", x, byte)
+            }
+            Instruction::SkipIfEqual { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRegisterRange { x, y } => write!(f, "SAVE V{:X} - V{:X}", x, y),
+            Instruction::ReadRegisterRange { x, y } => write!(f, "LOAD V{:X} - V{:X}", x, y),
+            Instruction::LoadByte { x, byte } => write!(f, "LD V{:X}, {:#04X}", x, byte),
+            Instruction::AddByte { x, byte } => write!(f, "ADD V{:X}, {:#04X}", x, byte),
+            Instruction::Load { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubNot { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipIfNotEqual { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadIndex { address } => write!(f, "LD I, {:#05X}", address),
+            Instruction::JumpPlusV0 { address } => write!(f, "JP V0, {:#05X}", address),
+            Instruction::Random { x, byte } => write!(f, "RND V{:X}, {:#04X}", x, byte),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipIfKeyDown { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipIfKeyUp { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadIndexLong => write!(f, "LD I, LONG"),
+            Instruction::SelectPlanes { n } => write!(f, "PLANE {}", n),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::ReadDelayTimer { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitForKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelayTimer { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSoundTimer { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIndex { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadCharacter { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::LoadBigCharacter { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::SetPitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::StoreRegisters { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::ReadRegisters { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::ReadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn decode_test() {
+        assert_eq!(Instruction::decode(0x00E0), Some(Instruction::ClearScreen));
+        assert_eq!(
+            Instruction::decode(0x22A0),
+            Some(Instruction::Call { address: 0x2A0 })
+        );
+        assert_eq!(
+            Instruction::decode(0xD01F),
+            Some(Instruction::Draw { x: 0, y: 1, n: 0xF })
+        );
+        assert_eq!(
+            Instruction::decode(0xF201),
+            Some(Instruction::SelectPlanes { n: 2 })
+        );
+        assert_eq!(Instruction::decode(0x0123), None);
+        assert_eq!(Instruction::decode(0x5AB1), None);
+        assert_eq!(Instruction::decode(0xF100), None);
+        assert_eq!(Instruction::decode(0xFFFF), None);
+    }
+
+    #[test]
+    pub fn display_test() {
+        let mnemonics: Vec<String> = [0x00EE, 0x3A12, 0x8AB6, 0xB300, 0xD125, 0xF355, 0xF000]
+            .iter()
+            .map(|opcode| Instruction::decode(*opcode).unwrap().to_string())
+            .collect();
+        assert_eq!(
+            mnemonics,
+            [
+                "RET",
+                "SE VA, 0x12",
+                "SHR VA, VB",
+                "JP V0, 0x300",
+                "DRW V1, V2, 5",
+                "LD [I], V3",
+                "LD I, LONG"
+            ]
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use crate::chip8::KEY_COUNT;
+use std::collections::HashSet;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The usual layout of the hex keypad on the left of a QWERTY keyboard:
+// 1 2 3 C      1 2 3 4
+// 4 5 6 D  ->  Q W E R
+// 7 8 9 E      A S D F
+// A 0 B F      Z X C V
+// The first controller moves like W, A, S and D, and its A button is E.
+const DEFAULT_LAYOUT: [&[&str]; KEY_COUNT as usize] = [
+    &["X"],
+    &["1"],
+    &["2"],
+    &["3"],
+    &["Q"],
+    &["W", "Pad1 DPadUp", "Pad1 LeftStickUp"],
+    &["E", "Pad1 A"],
+    &["A", "Pad1 DPadLeft", "Pad1 LeftStickLeft"],
+    &["S", "Pad1 DPadDown", "Pad1 LeftStickDown"],
+    &["D", "Pad1 DPadRight", "Pad1 LeftStickRight"],
+    &["Z"],
+    &["C"],
+    &["4"],
+    &["R"],
+    &["F"],
+    &["V"],
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeymapError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for KeymapError {}
+
+// Binds physical keys, by name, to the keys of the hex keypad.
+// A keymap file has one line per hex key, listing the physical keys bound to it:
+// # Comments start with #
+// C = 4, Keypad 4
+// Lines after a [NAME] header only apply to the ROM with that file name, and replace the
+// bindings of the keys they list.
+// Names are compared without case, the frontends decide which names they understand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: [Vec<String>; KEY_COUNT as usize],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap {
+            bindings: Default::default(),
+        };
+        for (key, names) in DEFAULT_LAYOUT.iter().enumerate() {
+            keymap.bindings[key] = names.iter().map(|name| name.to_string()).collect();
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    // Reads a keymap file over the default layout, applying the section of rom_name if any.
+    pub fn parse(text: &str, rom_name: &str) -> Result<Keymap, KeymapError> {
+        let mut keymap = Keymap::default();
+        let mut rom_bindings: Vec<(u8, Vec<String>)> = Vec::new();
+        // None outside of any section
+        let mut section: Option<String> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| KeymapError {
+                line: index + 1,
+                message,
+            };
+            let line: &str = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                match name.strip_suffix(']') {
+                    Some(name) => section = Some(name.trim().to_string()),
+                    None => return Err(error(format!("Unclosed section {}", line))),
+                }
+                continue;
+            }
+
+            let (key, names) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("Expected KEY = names, found {}", line)))?;
+            let key: u8 = u8::from_str_radix(key.trim(), 16)
+                .ok()
+                .filter(|key| *key < KEY_COUNT)
+                .ok_or_else(|| error(format!("Invalid hex key {}", key.trim())))?;
+            let names: Vec<String> = names
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect();
+            match &section {
+                None => keymap.bind(key, names),
+                Some(section) if section.eq_ignore_ascii_case(rom_name) => {
+                    rom_bindings.push((key, names))
+                }
+                Some(_) => {}
+            }
+        }
+
+        // The ROM section wins over the lines above it, wherever it is in the file
+        for (key, names) in rom_bindings {
+            keymap.bind(key, names);
+        }
+        Ok(keymap)
+    }
+
+    // Replaces the bindings of key. A physical key only ever drives one hex key, so the
+    // names are unbound from the other keys.
+    pub fn bind(&mut self, key: u8, names: Vec<String>) {
+        for bindings in self.bindings.iter_mut() {
+            bindings.retain(|bound| !names.iter().any(|name| name.eq_ignore_ascii_case(bound)));
+        }
+        self.bindings[key as usize] = names;
+    }
+
+    // The hex key bound to the physical key called name
+    pub fn key_for(&self, name: &str) -> Option<u8> {
+        (0..KEY_COUNT).find(|key| {
+            self.bindings[*key as usize]
+                .iter()
+                .any(|bound| bound.eq_ignore_ascii_case(name))
+        })
+    }
+
+    pub fn bindings(&self, key: u8) -> &[String] {
+        &self.bindings[key as usize]
+    }
+}
+
+// Keeps track of the physical keys that are down, so a hex key bound to several of them
+// is only released once they are all up.
+#[derive(Debug, Default)]
+pub struct KeypadState {
+    // Names in lowercase
+    down: HashSet<String>,
+}
+
+impl KeypadState {
+    pub fn new() -> KeypadState {
+        KeypadState::default()
+    }
+
+    // Records the physical key called name going down or up. Returns the hex key bound to it
+    // and whether it is pressed now, or None when nothing changed.
+    pub fn set(&mut self, keymap: &Keymap, name: &str, is_down: bool) -> Option<(u8, bool)> {
+        let name: String = name.to_ascii_lowercase();
+        let has_changed: bool = if is_down {
+            self.down.insert(name.clone())
+        } else {
+            self.down.remove(&name)
+        };
+        if !has_changed {
+            return None;
+        }
+        let key: u8 = keymap.key_for(&name)?;
+        let is_pressed: bool = keymap
+            .bindings(key)
+            .iter()
+            .any(|bound| self.down.contains(&bound.to_ascii_lowercase()));
+        Some((key, is_pressed))
+    }
+
+    // Puts up every physical key whose name starts with prefix, like the buttons of an
+    // unplugged controller. Returns the hex keys that changed, as set does.
+    pub fn release_all(&mut self, keymap: &Keymap, prefix: &str) -> Vec<(u8, bool)> {
+        let prefix: String = prefix.to_ascii_lowercase();
+        let names: Vec<String> = self
+            .down
+            .iter()
+            .filter(|name| name.starts_with(&prefix))
+            .cloned()
+            .collect();
+        names
+            .iter()
+            .filter_map(|name| self.set(keymap, name, false))
+            .collect()
+    }
+}
+
+// $XDG_CONFIG_HOME/rusty-chip/keymap, or ~/.config/rusty-chip/keymap
+pub fn default_keymap_path() -> Option<PathBuf> {
+    let config_dir: PathBuf = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("rusty-chip").join("keymap"))
+}
+
+// Loads the keymap file given, or the one at the default path when it exists.
+// Without either, the default layout is used.
+pub fn load_keymap(
+    filename: Option<&str>,
+    cartridge_rom_filename: &str,
+) -> Result<Keymap, Box<dyn Error>> {
+    let path: PathBuf = match filename {
+        Some(filename) => PathBuf::from(filename),
+        None => match default_keymap_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Keymap::default()),
+        },
+    };
+    let text: String = fs::read_to_string(&path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    let rom_name: &str = Path::new(cartridge_rom_filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(cartridge_rom_filename);
+    Keymap::parse(&text, rom_name).map_err(|e| format!("{} {}", path.display(), e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn default_layout_test() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.key_for("1"), Some(0x1));
+        assert_eq!(keymap.key_for("4"), Some(0xC));
+        assert_eq!(keymap.key_for("q"), Some(0x4));
+        assert_eq!(keymap.key_for("X"), Some(0x0));
+        assert_eq!(keymap.key_for("V"), Some(0xF));
+        assert_eq!(keymap.key_for("5"), None);
+        assert_eq!(keymap.key_for("Pad1 DPadUp"), Some(0x5));
+        assert_eq!(keymap.key_for("Pad2 DPadUp"), None);
+    }
+
+    #[test]
+    pub fn parse_test() {
+        let text: &str = "
+            # Arrows for the snake games
+            5 = W, Up
+            8 = S, Down # and on the right side
+            [PONG]
+            1 = Up
+            4 = Down
+            [BRIX]
+            4 = Left
+        ";
+        let keymap: Keymap = Keymap::parse(text, "pong").unwrap();
+        assert_eq!(keymap.key_for("w"), Some(0x5));
+        assert_eq!(keymap.key_for("Up"), Some(0x1));
+        assert_eq!(keymap.key_for("Down"), Some(0x4));
+        // Q is no longer bound, Left belongs to the BRIX section
+        assert_eq!(keymap.key_for("Q"), None);
+        assert_eq!(keymap.key_for("Left"), None);
+        assert_eq!(keymap.bindings(0x8), ["S"]);
+
+        let keymap: Keymap = Keymap::parse(text, "MAZE").unwrap();
+        assert_eq!(keymap.key_for("Up"), Some(0x5));
+        assert_eq!(keymap.key_for("Q"), Some(0x4));
+    }
+
+    #[test]
+    pub fn parse_error_test() {
+        assert_eq!(
+            Keymap::parse("1 = 1\nG = Q", "PONG"),
+            Err(KeymapError {
+                line: 2,
+                message: String::from("Invalid hex key G")
+            })
+        );
+        assert_eq!(Keymap::parse("[PONG\n", "PONG").unwrap_err().line, 1);
+        assert!(Keymap::parse("1 Q", "PONG").is_err());
+    }
+
+    #[test]
+    pub fn keypad_state_test() {
+        let keymap = Keymap::default();
+        let mut keypad = KeypadState::new();
+        assert_eq!(keypad.set(&keymap, "W", true), Some((0x5, true)));
+        assert_eq!(keypad.set(&keymap, "W", true), None);
+        assert_eq!(keypad.set(&keymap, "Pad1 DPadUp", true), Some((0x5, true)));
+        // Still held by the controller
+        assert_eq!(keypad.set(&keymap, "w", false), Some((0x5, true)));
+        assert_eq!(keypad.set(&keymap, "Pad1 A", true), Some((0x6, true)));
+        assert_eq!(keypad.set(&keymap, "Pad1 B", true), None);
+
+        let mut released: Vec<(u8, bool)> = keypad.release_all(&keymap, "Pad1 ");
+        released.sort_unstable();
+        assert_eq!(released, [(0x5, false), (0x6, false)]);
+        assert_eq!(keypad.set(&keymap, "Pad1 B", false), None);
+    }
+}
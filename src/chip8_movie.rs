@@ -0,0 +1,372 @@
+use crate::chip8::{Chip8, KEY_COUNT};
+use crate::chip8_error::Chip8Error;
+use crate::chip8_quirks::Quirks;
+use crate::chip8_state::{StateReader, StateWriter};
+use crate::chip8_util::crc32;
+use std::error::Error;
+use std::fmt;
+
+// A .c8m movie has the same header as a save state, with its own magic, and this payload:
+// ROM CRC-32 (u32) | seed (u64) | quirks (6 bytes) | XO-CHIP (bool) | cycles per frame (u32)
+// | frame count (u32) | input count (u32) | (frame (u32), key mask (u16))*
+// | checkpoint count (u32) | (frame (u32), state CRC-32 (u32))*
+// Bit k of a key mask is set while key k is held.
+pub const MOVIE_MAGIC: &[u8; 8] = b"RCHIPMOV";
+pub const MOVIE_VERSION: u16 = 1;
+// Frames between two hashes of the machine state
+pub const CHECKPOINT_INTERVAL: u32 = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    // The file is not a movie this version can play.
+    Invalid { reason: &'static str },
+    // The ROM is not the one the movie was recorded with.
+    RomMismatch { expected: u32, actual: u32 },
+    // The machine no longer matches the recording, from this frame on.
+    Desync { frame: u32 },
+    // The machine failed to load the ROM.
+    Machine(Chip8Error),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Invalid { reason } => write!(f, "Invalid movie: {}", reason),
+            MovieError::RomMismatch { expected, actual } => write!(
+                f,
+                "The movie was recorded on the ROM with CRC-32 {:08X}, not {:08X}",
+                expected, actual
+            ),
+            MovieError::Desync { frame } => write!(f, "The playback desynced at frame {}", frame),
+            MovieError::Machine(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<Chip8Error> for MovieError {
+    fn from(e: Chip8Error) -> Self {
+        match e {
+            Chip8Error::InvalidSaveState { reason } => MovieError::Invalid { reason },
+            e => MovieError::Machine(e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MovieInput {
+    frame: u32,
+    key_mask: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Checkpoint {
+    frame: u32,
+    state_crc32: u32,
+}
+
+// The input of every frame of a run, along with what it takes to replay it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_crc32: u32,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub xo_chip: bool,
+    pub cycles_per_frame: usize,
+    pub frame_count: u32,
+    inputs: Vec<MovieInput>,
+    checkpoints: Vec<Checkpoint>,
+}
+
+fn key_mask(chip8: &Chip8) -> u16 {
+    (0..KEY_COUNT)
+        .filter(|key| chip8.is_key_pressed(*key))
+        .fold(0, |mask, key| mask | 1 << key)
+}
+
+fn state_crc32(chip8: &Chip8) -> u32 {
+    crc32(&chip8.save_state())
+}
+
+impl Movie {
+    // Builds a machine set up like the one the movie was recorded on, with the ROM loaded.
+    pub fn create_machine(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        let actual: u32 = crc32(rom);
+        if actual != self.rom_crc32 {
+            return Err(MovieError::RomMismatch {
+                expected: self.rom_crc32,
+                actual,
+            });
+        }
+        let mut chip8: Chip8 = if self.xo_chip {
+            Chip8::new_xo_chip(self.quirks)
+        } else {
+            Chip8::new(self.quirks)
+        };
+        chip8.set_seed(self.seed);
+        chip8.load_rom(rom)?;
+        Ok(chip8)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer: StateWriter = StateWriter::new();
+        writer.u32(self.rom_crc32);
+        writer.u64(self.seed);
+        self.quirks.write(&mut writer);
+        writer.bool(self.xo_chip);
+        writer.u32(self.cycles_per_frame as u32);
+        writer.u32(self.frame_count);
+        writer.u32(self.inputs.len() as u32);
+        for input in &self.inputs {
+            writer.u32(input.frame);
+            writer.u16(input.key_mask);
+        }
+        writer.u32(self.checkpoints.len() as u32);
+        for checkpoint in &self.checkpoints {
+            writer.u32(checkpoint.frame);
+            writer.u32(checkpoint.state_crc32);
+        }
+        writer.finish_with_header(MOVIE_MAGIC, MOVIE_VERSION)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        let mut reader: StateReader =
+            StateReader::open_with_header(data, MOVIE_MAGIC, MOVIE_VERSION)?;
+        let rom_crc32: u32 = reader.u32()?;
+        let seed: u64 = reader.u64()?;
+        let quirks: Quirks = Quirks::read(&mut reader)?;
+        let xo_chip: bool = reader.bool()?;
+        let cycles_per_frame: usize = reader.u32()? as usize;
+        if cycles_per_frame == 0 {
+            return Err(MovieError::Invalid {
+                reason: "no cycles per frame",
+            });
+        }
+        let frame_count: u32 = reader.u32()?;
+
+        let mut inputs: Vec<MovieInput> = Vec::new();
+        for _ in 0..reader.u32()? {
+            inputs.push(MovieInput {
+                frame: reader.u32()?,
+                key_mask: reader.u16()?,
+            });
+        }
+        let mut checkpoints: Vec<Checkpoint> = Vec::new();
+        for _ in 0..reader.u32()? {
+            checkpoints.push(Checkpoint {
+                frame: reader.u32()?,
+                state_crc32: reader.u32()?,
+            });
+        }
+        reader.finish()?;
+
+        Ok(Movie {
+            rom_crc32,
+            seed,
+            quirks,
+            xo_chip,
+            cycles_per_frame,
+            frame_count,
+            inputs,
+            checkpoints,
+        })
+    }
+}
+
+// Records a movie, call record_frame before running every frame
+pub struct MovieRecorder {
+    movie: Movie,
+    key_mask: u16,
+}
+
+impl MovieRecorder {
+    // The machine must be in the state the recording starts from, seeded with seed.
+    pub fn new(rom: &[u8], seed: u64, chip8: &Chip8, cycles_per_frame: usize) -> MovieRecorder {
+        MovieRecorder {
+            movie: Movie {
+                rom_crc32: crc32(rom),
+                seed,
+                quirks: chip8.quirks(),
+                xo_chip: chip8.is_xo_chip(),
+                cycles_per_frame,
+                frame_count: 0,
+                inputs: Vec::new(),
+                checkpoints: Vec::new(),
+            },
+            key_mask: 0,
+        }
+    }
+
+    // Records the keys held for the frame about to run.
+    pub fn record_frame(&mut self, chip8: &Chip8) {
+        let frame: u32 = self.movie.frame_count;
+        let key_mask: u16 = key_mask(chip8);
+        if key_mask != self.key_mask {
+            self.movie.inputs.push(MovieInput { frame, key_mask });
+            self.key_mask = key_mask;
+        }
+        if frame.is_multiple_of(CHECKPOINT_INTERVAL) {
+            self.movie.checkpoints.push(Checkpoint {
+                frame,
+                state_crc32: state_crc32(chip8),
+            });
+        }
+        self.movie.frame_count += 1;
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+// Plays a movie back, call play_frame before running every frame in place of the user input
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: u32,
+    next_input: usize,
+    next_checkpoint: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> MoviePlayer {
+        MoviePlayer {
+            movie,
+            frame: 0,
+            next_input: 0,
+            next_checkpoint: 0,
+        }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    // Every recorded frame was played.
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frame_count
+    }
+
+    // Sets the keys held for the frame about to run, then checks the machine still matches
+    // the recording.
+    pub fn play_frame(&mut self, chip8: &mut Chip8) -> Result<(), MovieError> {
+        if let Some(input) = self.movie.inputs.get(self.next_input) {
+            if input.frame == self.frame {
+                for key in 0..KEY_COUNT {
+                    if input.key_mask & (1 << key) != 0 {
+                        chip8.press_key(key)?;
+                    } else {
+                        chip8.release_key(key)?;
+                    }
+                }
+                self.next_input += 1;
+            }
+        }
+
+        if let Some(checkpoint) = self.movie.checkpoints.get(self.next_checkpoint) {
+            if checkpoint.frame == self.frame {
+                self.next_checkpoint += 1;
+                if checkpoint.state_crc32 != state_crc32(chip8) {
+                    return Err(MovieError::Desync { frame: self.frame });
+                }
+            }
+        }
+
+        self.frame += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_assembler::assemble;
+
+    // Draws random bytes at a position moved by keys 4 and 6
+    fn game() -> Vec<u8> {
+        assemble(
+            "
+            : main
+                v0 := 4
+                if v0 key then v1 += -1
+                v0 := 6
+                if v0 key then v1 += 1
+                v2 := random 0xFF
+                i := byte
+                save v2
+                sprite v1 v3 1
+                jump main
+            : byte
+                0
+            ",
+        )
+        .unwrap()
+    }
+
+    fn record(rom: &[u8]) -> (Movie, Vec<u8>) {
+        let mut chip8: Chip8 = Chip8::new(Quirks::default());
+        chip8.set_seed(99);
+        chip8.load_rom(rom).unwrap();
+        let mut recorder: MovieRecorder = MovieRecorder::new(rom, 99, &chip8, 20);
+        for frame in 0..200 {
+            match frame {
+                10 => chip8.press_key(6).unwrap(),
+                50 => chip8.release_key(6).unwrap(),
+                120 => chip8.press_key(4).unwrap(),
+                _ => {}
+            }
+            recorder.record_frame(&chip8);
+            chip8.run_frame(20).unwrap();
+        }
+        (recorder.finish(), chip8.save_state())
+    }
+
+    #[test]
+    pub fn playback_test() {
+        let rom: Vec<u8> = game();
+        let (movie, final_state) = record(&rom);
+        assert_eq!(movie.frame_count, 200);
+        assert_eq!(movie.inputs.len(), 3);
+        assert_eq!(movie.checkpoints.len(), 4);
+
+        let movie: Movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        let mut chip8: Chip8 = movie.create_machine(&rom).unwrap();
+        let cycles_per_frame: usize = movie.cycles_per_frame;
+        let mut player: MoviePlayer = MoviePlayer::new(movie);
+        while !player.is_finished() {
+            player.play_frame(&mut chip8).unwrap();
+            chip8.run_frame(cycles_per_frame).unwrap();
+        }
+        assert_eq!(chip8.save_state(), final_state);
+    }
+
+    #[test]
+    pub fn desync_test() {
+        let rom: Vec<u8> = game();
+        let (movie, _) = record(&rom);
+        let mut chip8: Chip8 = movie.create_machine(&rom).unwrap();
+        let mut player: MoviePlayer = MoviePlayer::new(movie);
+        let mut result: Result<(), MovieError> = Ok(());
+        while result.is_ok() && !player.is_finished() {
+            result = player.play_frame(&mut chip8);
+            chip8.run_frame(19).unwrap();
+        }
+        assert_eq!(result, Err(MovieError::Desync { frame: 60 }));
+    }
+
+    #[test]
+    pub fn rom_mismatch_test() {
+        let rom: Vec<u8> = game();
+        let (movie, _) = record(&rom);
+        assert!(matches!(
+            movie.create_machine(&[0x12, 0x00]),
+            Err(MovieError::RomMismatch { .. })
+        ));
+        assert_eq!(
+            Movie::from_bytes(b"RCHIPSAV"),
+            Err(MovieError::Invalid {
+                reason: "not a save state"
+            })
+        );
+    }
+}
@@ -0,0 +1,135 @@
+// Red, green and blue
+pub type Rgb = [u8; 3];
+
+// Palettes that can be picked by name, in the order they are cycled through
+pub const PALETTE_NAMES: [&str; 4] = ["grey", "green", "amber", "lcd"];
+
+// Colours of the display, indexed by the XO-CHIP planes lit in a pixel:
+// background, plane 1, plane 2, both planes.
+// CHIP-8 and SUPER-CHIP programs only use the first two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgb; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::two_tone([0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF])
+    }
+}
+
+fn mix(background: Rgb, foreground: Rgb, weight: u32) -> Rgb {
+    let mut color: Rgb = [0; 3];
+    for (channel, value) in color.iter_mut().enumerate() {
+        *value = ((background[channel] as u32 * (3 - weight) + foreground[channel] as u32 * weight)
+            / 3) as u8;
+    }
+    color
+}
+
+fn parse_color(text: &str) -> Option<Rgb> {
+    let digits: &str = text.trim().trim_start_matches('#');
+    if digits.len() != 6 {
+        return None;
+    }
+    let value: u32 = u32::from_str_radix(digits, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+impl Palette {
+    // The other plane colours are shades between the background and the foreground.
+    pub fn two_tone(background: Rgb, foreground: Rgb) -> Palette {
+        Palette {
+            colors: [
+                background,
+                foreground,
+                mix(background, foreground, 2),
+                mix(background, foreground, 1),
+            ],
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Palette> {
+        match name {
+            "grey" => Some(Palette::default()),
+            // Green phosphor monitor
+            "green" => Some(Palette::two_tone([0x05, 0x1A, 0x05], [0x33, 0xFF, 0x66])),
+            // Amber monochrome monitor
+            "amber" => Some(Palette::two_tone([0x1A, 0x0F, 0x00], [0xFF, 0xB0, 0x00])),
+            // The four shades of a handheld LCD
+            "lcd" => Some(Palette {
+                colors: [
+                    [0x9B, 0xBC, 0x0F],
+                    [0x0F, 0x38, 0x0F],
+                    [0x30, 0x62, 0x30],
+                    [0x8B, 0xAC, 0x0F],
+                ],
+            }),
+            _ => None,
+        }
+    }
+
+    // A palette name, or the background and foreground colours in hexadecimal separated by
+    // a comma, like #000000,#FFFFFF. Listing four colours also sets the XO-CHIP planes.
+    pub fn parse(text: &str) -> Option<Palette> {
+        if let Some(palette) = Palette::from_name(text) {
+            return Some(palette);
+        }
+        let colors: Vec<Rgb> = text
+            .split(',')
+            .map(parse_color)
+            .collect::<Option<Vec<Rgb>>>()?;
+        match colors[..] {
+            [background, foreground] => Some(Palette::two_tone(background, foreground)),
+            [background, plane1, plane2, both] => Some(Palette {
+                colors: [background, plane1, plane2, both],
+            }),
+            _ => None,
+        }
+    }
+
+    // Colour of a framebuffer pixel
+    pub fn color(&self, planes: u8) -> Rgb {
+        self.colors[(planes & 0b11) as usize]
+    }
+
+    pub fn background(&self) -> Rgb {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> Rgb {
+        self.colors[1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn default_palette_test() {
+        // The greys the display always had
+        let palette = Palette::default();
+        let greys: Vec<u8> = (0..4).map(|planes| palette.color(planes)[0]).collect();
+        assert_eq!(greys, [0x00, 0xFF, 0xAA, 0x55]);
+        for name in PALETTE_NAMES.iter() {
+            assert!(Palette::from_name(name).is_some());
+        }
+    }
+
+    #[test]
+    pub fn parse_test() {
+        assert_eq!(Palette::parse("amber"), Palette::from_name("amber"));
+        assert_eq!(
+            Palette::parse("#000000,#30C060"),
+            Some(Palette::two_tone([0, 0, 0], [0x30, 0xC0, 0x60]))
+        );
+        assert_eq!(
+            Palette::parse("112233, 445566, 778899, AABBCC").map(|p| p.color(0b11)),
+            Some([0xAA, 0xBB, 0xCC])
+        );
+        assert_eq!(Palette::parse("#000000"), None);
+        assert_eq!(Palette::parse("#000000,#FFF"), None);
+        assert_eq!(Palette::parse("purple"), None);
+    }
+}
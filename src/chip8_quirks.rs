@@ -0,0 +1,150 @@
+use crate::chip8_error::Chip8Error;
+use crate::chip8_state::{StateReader, StateWriter};
+
+// Where FX55 and FX65 leave I
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    Unchanged,
+    // I += X, pointing to the last register stored or loaded
+    ByX,
+    // I += X + 1, pointing after the last register stored or loaded
+    ByXPlusOne,
+}
+
+// Behaviours of ambiguous instructions that differ between CHIP8 interpreters.
+// ROMs are usually written against one interpreter, pick the profile matching it.
+// https://github.com/Timendus/chip8-test-suite#quirks-test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6 and 8XYE shift VY and store the result in VX, instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    // How far FX55 and FX65 move I.
+    pub load_store_increments_i: IndexIncrement,
+    // BNNN is read as BXNN and jumps to XNN plus VX, instead of NNN plus V0.
+    pub jump_uses_vx: bool,
+    // 8XY1, 8XY2 and 8XY3 set VF to 0.
+    pub vf_reset: bool,
+    // Sprites drawn across the edge of the display are cut, instead of wrapping around.
+    pub clip_sprites: bool,
+    // DXYN waits for the next frame, so at most one sprite is drawn per frame.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    // The original interpreter of the RCA COSMAC VIP
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: IndexIncrement::ByXPlusOne,
+            jump_uses_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true,
+        }
+    }
+
+    // CHIP-48 for the HP-48 calculators
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: IndexIncrement::ByX,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    // SUPER-CHIP 1.1 for the HP-48 calculators
+    pub fn super_chip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: IndexIncrement::Unchanged,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    // What most modern interpreters and ROMs expect
+    pub fn modern() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: IndexIncrement::Unchanged,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+
+    // XO-CHIP as implemented by Octo
+    pub fn xo_chip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: IndexIncrement::ByXPlusOne,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+
+    // Finds a profile by the name used on the command line.
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" => Some(Quirks::cosmac_vip()),
+            "chip48" => Some(Quirks::chip48()),
+            "schip" => Some(Quirks::super_chip()),
+            "xochip" => Some(Quirks::xo_chip()),
+            "modern" => Some(Quirks::modern()),
+            _ => None,
+        }
+    }
+}
+
+impl Quirks {
+    // One byte per quirk, in the order of the fields, for save states and movies.
+    // I increments are written 0 when unchanged, 1 by X + 1 and 2 by X.
+    pub(crate) fn write(&self, writer: &mut StateWriter) {
+        writer.bool(self.shift_uses_vy);
+        writer.u8(match self.load_store_increments_i {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::ByXPlusOne => 1,
+            IndexIncrement::ByX => 2,
+        });
+        writer.bool(self.jump_uses_vx);
+        writer.bool(self.vf_reset);
+        writer.bool(self.clip_sprites);
+        writer.bool(self.display_wait);
+    }
+
+    pub(crate) fn read(reader: &mut StateReader) -> Result<Quirks, Chip8Error> {
+        let shift_uses_vy: bool = reader.bool()?;
+        let load_store_increments_i: IndexIncrement = match reader.u8()? {
+            0 => IndexIncrement::Unchanged,
+            1 => IndexIncrement::ByXPlusOne,
+            2 => IndexIncrement::ByX,
+            _ => {
+                return Err(Chip8Error::InvalidSaveState {
+                    reason: "corrupted",
+                })
+            }
+        };
+        Ok(Quirks {
+            shift_uses_vy,
+            load_store_increments_i,
+            jump_uses_vx: reader.bool()?,
+            vf_reset: reader.bool()?,
+            clip_sprites: reader.bool()?,
+            display_wait: reader.bool()?,
+        })
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::modern()
+    }
+}
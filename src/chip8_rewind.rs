@@ -0,0 +1,213 @@
+use crate::chip8::Chip8;
+use std::collections::VecDeque;
+
+// Default memory budget of the rewind buffer, several minutes of most games
+pub const DEFAULT_REWIND_MEMORY: usize = 16 * 1024 * 1024;
+
+// Keeps one snapshot per frame so the game can be played backwards.
+// Only the latest save state is kept whole, every older frame is stored as the difference
+// with the frame after it: the XOR of both states, run length encoded since few bytes change
+// from one frame to the next. The oldest frames are dropped once the differences take more
+// than the memory budget.
+pub struct Rewind {
+    capacity: usize,
+    latest: Vec<u8>,
+    deltas: VecDeque<Vec<u8>>,
+    deltas_size: usize,
+}
+
+impl Rewind {
+    // capacity is the memory budget in bytes for the stored frames
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            capacity,
+            latest: Vec::new(),
+            deltas: VecDeque::new(),
+            deltas_size: 0,
+        }
+    }
+
+    // Records the state of the machine, call it once per frame.
+    pub fn push(&mut self, chip8: &Chip8) {
+        let state: Vec<u8> = chip8.save_state();
+        if !self.latest.is_empty() {
+            let delta: Vec<u8> = encode_delta(&self.latest, &state);
+            self.deltas_size += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.latest = state;
+
+        while self.deltas_size + self.latest.len() > self.capacity {
+            match self.deltas.pop_front() {
+                Some(delta) => self.deltas_size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    // Brings the machine back one frame, returns false once the oldest frame is reached.
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> bool {
+        let delta: Vec<u8> = match self.deltas.pop_back() {
+            Some(delta) => delta,
+            None => return false,
+        };
+        self.deltas_size -= delta.len();
+        self.latest = decode_delta(&self.latest, &delta);
+        // The states were made by save_state, they only fail to load if the buffer is broken
+        if chip8.load_state(&self.latest).is_err() {
+            self.clear();
+            return false;
+        }
+        true
+    }
+
+    // Amount of frames that can be rewound
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    // Bytes taken by the stored frames
+    pub fn memory_used(&self) -> usize {
+        self.deltas_size + self.latest.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest.clear();
+        self.deltas.clear();
+        self.deltas_size = 0;
+    }
+}
+
+// LEB128, 7 bits per byte with the high bit set on every byte but the last
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value: usize = 0;
+    let mut shift: u32 = 0;
+    loop {
+        let byte: u8 = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+// The length of older, then runs of unchanged bytes each followed by the XORed bytes
+// that changed: (unchanged count, changed count, changed bytes)*
+// States of different lengths are compared as if the shorter one was padded with zeroes.
+fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let length: usize = older.len().max(newer.len());
+    let xor = |index: usize| -> u8 {
+        older.get(index).copied().unwrap_or(0) ^ newer.get(index).copied().unwrap_or(0)
+    };
+
+    let mut delta: Vec<u8> = Vec::new();
+    write_varint(&mut delta, older.len());
+    let mut index: usize = 0;
+    while index < length {
+        let unchanged_start: usize = index;
+        while index < length && xor(index) == 0 {
+            index += 1;
+        }
+        if index == length {
+            break;
+        }
+        let changed_start: usize = index;
+        while index < length && xor(index) != 0 {
+            index += 1;
+        }
+        write_varint(&mut delta, changed_start - unchanged_start);
+        write_varint(&mut delta, index - changed_start);
+        delta.extend((changed_start..index).map(xor));
+    }
+    delta
+}
+
+// Rebuilds the older state from the newer one
+fn decode_delta(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut position: usize = 0;
+    let older_length: usize = read_varint(delta, &mut position);
+    let mut older: Vec<u8> = newer.to_vec();
+    older.resize(older_length.max(newer.len()), 0);
+
+    let mut index: usize = 0;
+    while position < delta.len() {
+        index += read_varint(delta, &mut position);
+        let changed: usize = read_varint(delta, &mut position);
+        for byte in &delta[position..(position + changed)] {
+            older[index] ^= byte;
+            index += 1;
+        }
+        position += changed;
+    }
+    older.truncate(older_length);
+    older
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    #[test]
+    pub fn delta_test() {
+        let older: Vec<u8> = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let newer: Vec<u8> = vec![1, 2, 9, 4, 5, 6, 0, 0, 10, 11];
+        let delta: Vec<u8> = encode_delta(&older, &newer);
+        assert_eq!(decode_delta(&newer, &delta), older);
+        assert_eq!(decode_delta(&older, &encode_delta(&newer, &older)), newer);
+        assert_eq!(encode_delta(&older, &older), [8]);
+    }
+
+    #[test]
+    pub fn rewind_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Counts up in V0 forever, switching to high resolution on the way
+        c.load_rom(&[0x70, 0x01, 0x00, 0xFF, 0x12, 0x00]).unwrap();
+        let mut rewind: Rewind = Rewind::new(DEFAULT_REWIND_MEMORY);
+
+        let mut states: Vec<Vec<u8>> = Vec::new();
+        for _ in 0..10 {
+            rewind.push(&c);
+            states.push(c.save_state());
+            c.run_frame(1).unwrap();
+        }
+        assert_eq!(rewind.len(), 9);
+
+        for state in states.iter().rev().skip(1) {
+            assert!(rewind.rewind(&mut c));
+            assert_eq!(&c.save_state(), state);
+        }
+        assert!(!rewind.rewind(&mut c));
+        assert_eq!(c.registers()[0], 0);
+    }
+
+    #[test]
+    pub fn capacity_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        c.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let state_size: usize = c.save_state().len();
+        let mut rewind: Rewind = Rewind::new(state_size + 100);
+
+        for _ in 0..1000 {
+            rewind.push(&c);
+            c.run_frame(1).unwrap();
+        }
+        assert!(rewind.memory_used() <= state_size + 100);
+        assert!(rewind.len() > 5);
+        assert!(rewind.len() < 1000);
+    }
+}
@@ -0,0 +1,58 @@
+// The random number generator behind CXNN.
+// SplitMix64 keeps its whole state in one u64, so a seed replays the same numbers and the
+// state fits in a save state.
+// https://prng.di.unimi.it/splitmix64.c
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    // Seeds the generator with a different number on every run
+    pub fn from_entropy() -> Rng {
+        Rng::new(rand::random())
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z: u64 = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn splitmix64_test() {
+        // First outputs of the reference implementation seeded with 1234567
+        let mut rng: Rng = Rng::new(1234567);
+        assert_eq!(rng.next_u64(), 6457827717110365317);
+        assert_eq!(rng.next_u64(), 3203168211198807973);
+    }
+
+    #[test]
+    pub fn same_seed_test() {
+        let mut a: Rng = Rng::new(42);
+        let mut b: Rng = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u8(), b.next_u8());
+        }
+        assert_eq!(a.state(), b.state());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::chip8::{MEMORY_SIZE, PROGRAM_START, XO_CHIP_MEMORY_SIZE};
+use crate::chip8_util::{crc32, sha1};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// Programs are loaded at 0x200, they can fill the rest of the memory
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - PROGRAM_START;
+pub const XO_CHIP_MAX_ROM_SIZE: usize = XO_CHIP_MEMORY_SIZE - PROGRAM_START;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    // The file could not be read.
+    Io { message: String },
+    Empty,
+    // The program does not fit in the memory of the machine it is meant for.
+    TooLarge { size: usize, max_size: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io { message } => write!(f, "{}", message),
+            RomError::Empty => write!(f, "The ROM is empty"),
+            RomError::TooLarge { size, max_size } if *max_size == MAX_ROM_SIZE => write!(
+                f,
+                "The ROM is {} bytes, more than the {} bytes a CHIP-8 program can use, \
+                 XO-CHIP programs can use up to {} bytes with --xo-chip",
+                size, max_size, XO_CHIP_MAX_ROM_SIZE
+            ),
+            RomError::TooLarge { size, max_size } => write!(
+                f,
+                "The ROM is {} bytes, more than the {} bytes an XO-CHIP program can use",
+                size, max_size
+            ),
+        }
+    }
+}
+
+impl Error for RomError {}
+
+// A program checked to fit in the memory of the machine, along with its hashes.
+// Instructions are 2 bytes long, so a ROM of odd length may have been cut short, although
+// programs holding data after their code can be any length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rom {
+    bytes: Vec<u8>,
+    crc32: u32,
+    sha1: [u8; 20],
+}
+
+impl Rom {
+    pub fn from_bytes(bytes: Vec<u8>, xo_chip: bool) -> Result<Rom, RomError> {
+        let max_size: usize = if xo_chip {
+            XO_CHIP_MAX_ROM_SIZE
+        } else {
+            MAX_ROM_SIZE
+        };
+        if bytes.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if bytes.len() > max_size {
+            return Err(RomError::TooLarge {
+                size: bytes.len(),
+                max_size,
+            });
+        }
+        Ok(Rom {
+            crc32: crc32(&bytes),
+            sha1: sha1(&bytes),
+            bytes,
+        })
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P, xo_chip: bool) -> Result<Rom, RomError> {
+        let bytes: Vec<u8> = fs::read(path).map_err(|e| RomError::Io {
+            message: e.to_string(),
+        })?;
+        Rom::from_bytes(bytes, xo_chip)
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    // Never true, empty ROMs are rejected
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn is_odd_length(&self) -> bool {
+        self.bytes.len() % 2 == 1
+    }
+
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    pub fn sha1(&self) -> [u8; 20] {
+        self.sha1
+    }
+
+    // In lowercase hexadecimal, as ROM databases list it
+    pub fn sha1_hex(&self) -> String {
+        self.sha1
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    // Size and hashes, to identify the ROM in bug reports
+    pub fn describe(&self) -> String {
+        format!(
+            "{} bytes, CRC-32 {:08X}, SHA-1 {}",
+            self.len(),
+            self.crc32,
+            self.sha1_hex()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn from_bytes_test() {
+        let rom: Rom = Rom::from_bytes(b"123456789".to_vec(), false).unwrap();
+        assert_eq!(rom.bytes(), b"123456789");
+        assert_eq!(rom.crc32(), 0xCBF4_3926);
+        assert_eq!(rom.sha1_hex(), "f7c3bc1d808e04732adf679965ccc34ca7ae3441");
+        assert!(rom.is_odd_length());
+        assert_eq!(
+            rom.describe(),
+            "9 bytes, CRC-32 CBF43926, SHA-1 f7c3bc1d808e04732adf679965ccc34ca7ae3441"
+        );
+        assert!(!Rom::from_bytes(vec![0x00, 0xE0], false)
+            .unwrap()
+            .is_odd_length());
+    }
+
+    #[test]
+    pub fn from_bytes_error_test() {
+        assert_eq!(Rom::from_bytes(Vec::new(), true), Err(RomError::Empty));
+        assert!(Rom::from_bytes(vec![0; MAX_ROM_SIZE], false).is_ok());
+        assert_eq!(
+            Rom::from_bytes(vec![0; MAX_ROM_SIZE + 1], false),
+            Err(RomError::TooLarge {
+                size: 3585,
+                max_size: 3584
+            })
+        );
+        // XO-CHIP machines have room for it
+        assert!(Rom::from_bytes(vec![0; MAX_ROM_SIZE + 1], true).is_ok());
+        assert!(Rom::from_bytes(vec![0; XO_CHIP_MAX_ROM_SIZE + 1], true).is_err());
+    }
+
+    #[test]
+    pub fn from_path_test() {
+        let rom: Rom = Rom::from_path("roms/PONG", false).unwrap();
+        assert!(!rom.is_empty());
+        match Rom::from_path("roms/MISSING", false) {
+            Err(RomError::Io { .. }) => {}
+            result => panic!("Expected an IO error, got {:?}", result),
+        }
+    }
+}
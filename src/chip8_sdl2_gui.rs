@@ -1,21 +1,105 @@
-use crate::chip8::Chip8;
+use rusty_chip::chip8::{
+    Chip8, DEFAULT_CYCLES_PER_FRAME, DISPLAY_HEIGHT, DISPLAY_WIDTH, FRAMES_PER_SECOND,
+};
+use rusty_chip::chip8_capture::GifRecorder;
+use rusty_chip::chip8_debugger::{DebugCommand, DebugStop, Debugger};
+use rusty_chip::chip8_error::Chip8Error;
+use rusty_chip::chip8_font::{Font, FONT_SIZE};
+use rusty_chip::chip8_image::render_framebuffer;
+use rusty_chip::chip8_keymap::{load_keymap, Keymap, KeypadState};
+use rusty_chip::chip8_movie::{Movie, MoviePlayer, MovieRecorder};
+use rusty_chip::chip8_palette::{Palette, Rgb, PALETTE_NAMES};
+use rusty_chip::chip8_rewind::{Rewind, DEFAULT_REWIND_MEMORY};
+use rusty_chip::chip8_rom::Rom;
+use rusty_chip::chip8_sound::{
+    FrameSound, SoundSettings, Synthesizer, Waveform, DEFAULT_SAMPLE_RATE,
+};
+use rusty_chip::Quirks;
 use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::controller::{Axis, Button, GameController};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
 use sdl2::render;
+use sdl2::GameControllerSubsystem;
+use std::convert::TryInto;
 use std::error::Error;
 use std::fs;
-use std::fs::File;
-use std::io::Read;
-use std::time::Duration;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
+// Size of a pixel in low resolution, high resolution pixels are half as big so the window
+// keeps the same size in both resolutions.
 const PIXEL_SIZE: u32 = 10;
+// When the emulator falls behind by more frames than this, the late frames are dropped
+// instead of being run all at once.
+const MAX_FRAMES_BEHIND: u32 = 5;
+// Analog sticks and triggers count as pressed past half of their range
+const AXIS_THRESHOLD: i16 = 16384;
+
+// How the pixels of the display are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelStyle {
+    Square,
+    // Thin lines separate the pixels
+    Grid,
+    // Pixels have their corners cut off
+    Rounded,
+}
+
+impl PixelStyle {
+    pub fn from_name(name: &str) -> Option<PixelStyle> {
+        match name {
+            "square" => Some(PixelStyle::Square),
+            "grid" => Some(PixelStyle::Grid),
+            "rounded" => Some(PixelStyle::Rounded),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PixelStyle::Square => "square",
+            PixelStyle::Grid => "grid",
+            PixelStyle::Rounded => "rounded",
+        }
+    }
+
+    fn next(self) -> PixelStyle {
+        match self {
+            PixelStyle::Square => PixelStyle::Grid,
+            PixelStyle::Grid => PixelStyle::Rounded,
+            PixelStyle::Rounded => PixelStyle::Square,
+        }
+    }
+}
 
 pub struct Config {
     pub cartridge_rom_filename: String,
-    pub console_rom_filename: String,
+    // Replaces the built-in hexadecimal font, the file holds the 80 bytes of the 16 sprites
+    pub font_filename: Option<String>,
+    pub quirks: Quirks,
+    pub cycles_per_frame: usize,
+    pub xo_chip: bool,
+    // Starts paused with a debugger prompt on stdin
+    pub debug: bool,
+    // Memory budget of the rewind buffer in bytes, 0 disables rewinding
+    pub rewind_memory: usize,
+    // Seed of the random number generator, a different one is picked on every run otherwise
+    pub seed: Option<u64>,
+    // Records the keypad input to a .c8m movie, written when the emulator quits
+    pub record_filename: Option<String>,
+    // Replays a .c8m movie in place of the keypad input
+    pub play_filename: Option<String>,
+    // Binds the keyboard to the hex keypad, see chip8_keymap for the format
+    pub keymap_filename: Option<String>,
+    pub palette: Palette,
+    pub pixel_style: PixelStyle,
+    pub sound: SoundSettings,
 }
 impl Config {
     pub fn new(args: &[String]) -> Result<Config, &'static str> {
@@ -24,77 +108,517 @@ impl Config {
         }
 
         let cartridge_rom_filename = args[1].clone();
+        let mut font_filename: Option<String> = None;
+        let mut quirks: Option<Quirks> = None;
+        let mut cycles_per_frame: usize = DEFAULT_CYCLES_PER_FRAME;
+        let mut xo_chip: bool = false;
+        let mut debug: bool = false;
+        let mut rewind_memory: usize = DEFAULT_REWIND_MEMORY;
+        let mut seed: Option<u64> = None;
+        let mut record_filename: Option<String> = None;
+        let mut play_filename: Option<String> = None;
+        let mut keymap_filename: Option<String> = None;
+        let mut palette: Palette = Palette::default();
+        let mut pixel_style: PixelStyle = PixelStyle::Square;
+        let mut sound: SoundSettings = SoundSettings::default();
+
+        let mut options = args[2..].iter();
+        while let Some(option) = options.next() {
+            match option.as_str() {
+                "--font" => match options.next() {
+                    Some(filename) => font_filename = Some(filename.clone()),
+                    None => return Err("--font requires a filename"),
+                },
+                "--quirks" => match options.next().and_then(|name| Quirks::from_name(name)) {
+                    Some(profile) => quirks = Some(profile),
+                    None => {
+                        return Err("--quirks requires one of vip, chip48, schip, xochip or modern")
+                    }
+                },
+                "--cycles-per-frame" => match options.next().and_then(|n| n.parse().ok()) {
+                    Some(cycles) if cycles > 0 => cycles_per_frame = cycles,
+                    _ => return Err("--cycles-per-frame requires a positive number"),
+                },
+                "--xo-chip" => xo_chip = true,
+                "--debug" => debug = true,
+                "--seed" => match options.next().and_then(|n| n.parse().ok()) {
+                    Some(number) => seed = Some(number),
+                    None => return Err("--seed requires a number"),
+                },
+                "--rewind-memory" => match options.next().and_then(|n| n.parse::<usize>().ok()) {
+                    Some(mebibytes) => rewind_memory = mebibytes * 1024 * 1024,
+                    None => return Err("--rewind-memory requires a size in MiB"),
+                },
+                "--record" => match options.next() {
+                    Some(filename) => record_filename = Some(filename.clone()),
+                    None => return Err("--record requires a filename"),
+                },
+                "--play" => match options.next() {
+                    Some(filename) => play_filename = Some(filename.clone()),
+                    None => return Err("--play requires a filename"),
+                },
+                "--keymap" => match options.next() {
+                    Some(filename) => keymap_filename = Some(filename.clone()),
+                    None => return Err("--keymap requires a filename"),
+                },
+                "--palette" => match options.next().and_then(|p| Palette::parse(p)) {
+                    Some(colors) => palette = colors,
+                    None => return Err("--palette requires a palette name or #RRGGBB,#RRGGBB"),
+                },
+                "--pixel-style" => match options.next().and_then(|s| PixelStyle::from_name(s)) {
+                    Some(style) => pixel_style = style,
+                    None => return Err("--pixel-style requires one of square, grid or rounded"),
+                },
+                "--tone" => match options.next().and_then(|n| n.parse::<f32>().ok()) {
+                    Some(hertz) if hertz > 0.0 && hertz < 20000.0 => sound.frequency = hertz,
+                    _ => return Err("--tone requires a frequency in Hz below 20000"),
+                },
+                "--waveform" => match options.next().and_then(|w| Waveform::from_name(w)) {
+                    Some(waveform) => sound.waveform = waveform,
+                    None => {
+                        return Err("--waveform requires one of square, triangle, sine or noise")
+                    }
+                },
+                "--volume" => match options.next().and_then(|n| n.parse::<u8>().ok()) {
+                    Some(percent) if percent <= 100 => sound.volume = percent as f32 / 100.0,
+                    _ => return Err("--volume requires a percentage from 0 to 100"),
+                },
+                "--mute" => sound.is_muted = true,
+                _ => return Err("unknown option"),
+            }
+        }
+        if record_filename.is_some() && play_filename.is_some() {
+            return Err("--record and --play cannot be used together");
+        }
+
+        // XO-CHIP programs expect the quirks of Octo unless told otherwise
+        let quirks: Quirks = quirks.unwrap_or_else(|| {
+            if xo_chip {
+                Quirks::xo_chip()
+            } else {
+                Quirks::default()
+            }
+        });
 
         Ok(Config {
-            cartridge_rom_filename: cartridge_rom_filename,
-            console_rom_filename: String::from("console_rom.dat"),
+            cartridge_rom_filename,
+            font_filename,
+            quirks,
+            cycles_per_frame,
+            xo_chip,
+            debug,
+            rewind_memory,
+            seed,
+            record_filename,
+            play_filename,
+            keymap_filename,
+            palette,
+            pixel_style,
+            sound,
         })
     }
 }
 
-fn index_to_point(index: i32) -> Point {
+fn index_to_point(index: i32, display_width: i32, pixel_size: u32) -> Point {
     Point::new(
-        (index % 64) * PIXEL_SIZE as i32,
-        (index / 64) * PIXEL_SIZE as i32,
+        (index % display_width) * pixel_size as i32,
+        (index / display_width) * pixel_size as i32,
     )
 }
 
-fn set_grid_index_color(canvas: &mut render::WindowCanvas, index: i32, alpha: u8) {
-    canvas.set_draw_color(Color::RGBA(alpha, alpha, alpha, 255));
-    let point: Point = index_to_point(index);
-    match canvas.fill_rect(Rect::new(point.x, point.y, PIXEL_SIZE, PIXEL_SIZE)) {
-        Err(e) => println!("{:?}", e),
-        _ => (),
+// Controllers are told apart by player number, their buttons are named like "Pad1 A" in keymaps
+fn button_name(button: Button) -> &'static str {
+    match button {
+        Button::A => "A",
+        Button::B => "B",
+        Button::X => "X",
+        Button::Y => "Y",
+        Button::Back => "Back",
+        Button::Guide => "Guide",
+        Button::Start => "Start",
+        Button::LeftStick => "LeftStick",
+        Button::RightStick => "RightStick",
+        Button::LeftShoulder => "LeftShoulder",
+        Button::RightShoulder => "RightShoulder",
+        Button::DPadUp => "DPadUp",
+        Button::DPadDown => "DPadDown",
+        Button::DPadLeft => "DPadLeft",
+        Button::DPadRight => "DPadRight",
     }
 }
 
-fn get_file_as_byte_vec(filename: &str) -> Vec<u8> {
-    let mut f = File::open(&filename).expect(&format!("File named {} was not found!", filename));
-    let metadata = fs::metadata(&filename).expect(&format!(
-        "Unable to read metadata for file named {}",
-        filename
-    ));
-    let mut buffer = vec![0; metadata.len() as usize];
-    f.read(&mut buffer).expect("buffer overflow");
+// Names of the negative and positive directions of an axis, triggers only have the latter
+fn axis_names(axis: Axis) -> (Option<&'static str>, &'static str) {
+    match axis {
+        Axis::LeftX => (Some("LeftStickLeft"), "LeftStickRight"),
+        Axis::LeftY => (Some("LeftStickUp"), "LeftStickDown"),
+        Axis::RightX => (Some("RightStickLeft"), "RightStickRight"),
+        Axis::RightY => (Some("RightStickUp"), "RightStickDown"),
+        Axis::TriggerLeft => (None, "LeftTrigger"),
+        Axis::TriggerRight => (None, "RightTrigger"),
+    }
+}
 
-    buffer
+fn player_prefix(player: usize) -> String {
+    format!("Pad{} ", player)
 }
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32,
+
+// The controllers plugged in, player 1 first. An unplugged controller leaves its slot empty
+// so the other players keep their number.
+struct Controllers {
+    slots: Vec<Option<GameController>>,
 }
 
-impl AudioCallback for SquareWave {
-    type Channel = f32;
+impl Controllers {
+    // Returns the player number given to the controller
+    fn add(
+        &mut self,
+        subsystem: &GameControllerSubsystem,
+        joystick_index: u32,
+    ) -> Result<usize, String> {
+        let controller: GameController =
+            subsystem.open(joystick_index).map_err(|e| e.to_string())?;
+        match self.slots.iter().position(Option::is_none) {
+            Some(slot) => {
+                self.slots[slot] = Some(controller);
+                Ok(slot + 1)
+            }
+            None => {
+                self.slots.push(Some(controller));
+                Ok(self.slots.len())
+            }
+        }
+    }
 
-    fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
-        for x in out.iter_mut() {
-            if self.phase >= 0.0 && self.phase <= 0.5 {
-                *x = self.volume
-            } else {
-                *x = -self.volume
+    // Returns the player number the controller had
+    fn remove(&mut self, instance_id: u32) -> Option<usize> {
+        let player: usize = self.player(instance_id)?;
+        self.slots[player - 1] = None;
+        Some(player)
+    }
+
+    fn player(&self, instance_id: u32) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| {
+                slot.as_ref()
+                    .is_some_and(|controller| controller.instance_id() == instance_id)
+            })
+            .map(|slot| slot + 1)
+    }
+}
+
+// The controller inputs an event moves, with whether they are down now
+fn controller_inputs(event: &Event, controllers: &Controllers) -> Vec<(String, bool)> {
+    let name = |which: u32, name: &str| -> Option<String> {
+        let player: usize = controllers.player(which)?;
+        Some(format!("{}{}", player_prefix(player), name))
+    };
+    match *event {
+        Event::ControllerButtonDown { which, button, .. } => name(which, button_name(button))
+            .map(|name| vec![(name, true)])
+            .unwrap_or_default(),
+        Event::ControllerButtonUp { which, button, .. } => name(which, button_name(button))
+            .map(|name| vec![(name, false)])
+            .unwrap_or_default(),
+        Event::ControllerAxisMotion {
+            which, axis, value, ..
+        } => {
+            let (negative, positive) = axis_names(axis);
+            let mut inputs: Vec<(String, bool)> = Vec::new();
+            if let Some(negative) = negative.and_then(|negative| name(which, negative)) {
+                inputs.push((negative, value < -AXIS_THRESHOLD));
+            }
+            if let Some(positive) = name(which, positive) {
+                inputs.push((positive, value > AXIS_THRESHOLD));
             }
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+            inputs
         }
+        _ => Vec::new(),
+    }
+}
+
+fn apply_keypad_change(chip8: &mut Chip8, change: (u8, bool)) -> Result<(), Chip8Error> {
+    match change {
+        (key, true) => chip8.press_key(key),
+        (key, false) => chip8.release_key(key),
+    }
+}
+
+// F1 to F4 load the save state slots, with shift held they save them
+fn keycode_to_save_slot(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        _ => None,
+    }
+}
+
+// Save states are kept next to the ROM, as <rom>.state1 to <rom>.state4
+fn save_slot_filename(cartridge_rom_filename: &str, slot: u8) -> String {
+    format!("{}.state{}", cartridge_rom_filename, slot)
+}
+
+fn save_to_slot(chip8: &Chip8, cartridge_rom_filename: &str, slot: u8) {
+    let filename: String = save_slot_filename(cartridge_rom_filename, slot);
+    match fs::write(&filename, chip8.save_state()) {
+        Ok(()) => println!("Saved slot {} to {}", slot, filename),
+        Err(e) => eprintln!("Unable to save slot {} to {}: {}", slot, filename, e),
+    }
+}
+
+// Screenshots and GIFs are kept next to the ROM too, as <rom>-1.png, <rom>-2.gif and so on
+fn next_capture_filename(cartridge_rom_filename: &str, extension: &str) -> String {
+    let mut number: u32 = 1;
+    while Path::new(&format!(
+        "{}-{}.{}",
+        cartridge_rom_filename, number, extension
+    ))
+    .exists()
+    {
+        number += 1;
+    }
+    format!("{}-{}.{}", cartridge_rom_filename, number, extension)
+}
+
+// Screenshots have the size of the window, in the current palette
+fn save_screenshot(chip8: &Chip8, cartridge_rom_filename: &str, palette: &Palette) {
+    let filename: String = next_capture_filename(cartridge_rom_filename, "png");
+    let scale: usize = PIXEL_SIZE as usize * DISPLAY_WIDTH / chip8.display_width();
+    let png: Vec<u8> = render_framebuffer(chip8, palette, scale).to_png();
+    match fs::write(&filename, png) {
+        Ok(()) => println!("Saved screenshot to {}", filename),
+        Err(e) => eprintln!("Unable to save screenshot to {}: {}", filename, e),
+    }
+}
+
+fn save_gif(recorder: GifRecorder, cartridge_rom_filename: &str) {
+    let filename: String = next_capture_filename(cartridge_rom_filename, "gif");
+    let frame_count: usize = recorder.frame_count();
+    match fs::write(&filename, recorder.finish()) {
+        Ok(()) => println!("Saved {} frames to {}", frame_count, filename),
+        Err(e) => eprintln!("Unable to save the GIF to {}: {}", filename, e),
+    }
+}
+
+fn load_from_slot(chip8: &mut Chip8, cartridge_rom_filename: &str, slot: u8) {
+    let filename: String = save_slot_filename(cartridge_rom_filename, slot);
+    let result: Result<(), Box<dyn Error>> = fs::read(&filename)
+        .map_err(|e| e.into())
+        .and_then(|state| chip8.load_state(&state).map_err(|e| e.into()));
+    match result {
+        Ok(()) => println!("Loaded slot {} from {}", slot, filename),
+        Err(e) => eprintln!("Unable to load slot {} from {}: {}", slot, filename, e),
+    }
+}
+
+// Debugger hotkeys, F5 also pauses a running program
+fn keycode_to_debug_command(keycode: Keycode) -> Option<DebugCommand> {
+    match keycode {
+        Keycode::F5 => Some(DebugCommand::Continue),
+        Keycode::F6 => Some(DebugCommand::Step),
+        Keycode::F7 => Some(DebugCommand::StepOver),
+        Keycode::F8 => Some(DebugCommand::StepOut),
+        Keycode::F10 => Some(DebugCommand::Dump),
+        _ => None,
+    }
+}
+
+// Reads debugger commands from stdin on another thread, so the window stays responsive
+// while the prompt waits for input.
+fn spawn_debug_prompt() -> mpsc::Receiver<DebugCommand> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = std::io::stdin();
+        for line in stdin.lock().lines() {
+            let line: String = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match DebugCommand::parse(&line) {
+                Ok(command) => {
+                    if sender.send(command).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => println!("{}", e),
+            }
+        }
+    });
+    receiver
+}
+
+fn print_debug_output(output: &str) {
+    if !output.is_empty() {
+        println!("{}", output);
+    }
+    print!("(debug) ");
+    let _ = std::io::stdout().flush();
+}
+
+fn sdl_color([r, g, b]: Rgb) -> Color {
+    Color::RGB(r, g, b)
+}
+
+// What shows between the pixels: the background, or with the grid style a colour a little
+// closer to the foreground
+fn clear_color(palette: &Palette, pixel_style: PixelStyle) -> Color {
+    let background: Rgb = palette.background();
+    if pixel_style != PixelStyle::Grid {
+        return sdl_color(background);
+    }
+    let foreground: Rgb = palette.foreground();
+    let mut line: Rgb = background;
+    for (channel, value) in line.iter_mut().enumerate() {
+        *value = ((background[channel] as u32 * 7 + foreground[channel] as u32) / 8) as u8;
+    }
+    sdl_color(line)
+}
+
+fn set_grid_index_color(
+    canvas: &mut render::WindowCanvas,
+    index: i32,
+    planes: u8,
+    display_width: usize,
+    palette: &Palette,
+    pixel_style: PixelStyle,
+) {
+    canvas.set_draw_color(sdl_color(palette.color(planes)));
+    let pixel_size: u32 = PIXEL_SIZE * DISPLAY_WIDTH as u32 / display_width as u32;
+    let point: Point = index_to_point(index, display_width as i32, pixel_size);
+    let rects: Vec<Rect> = match pixel_style {
+        PixelStyle::Square => vec![Rect::new(point.x, point.y, pixel_size, pixel_size)],
+        PixelStyle::Grid => vec![Rect::new(point.x, point.y, pixel_size - 1, pixel_size - 1)],
+        // A cross of two rectangles leaves the corners out
+        PixelStyle::Rounded => {
+            let inset: u32 = pixel_size / 4;
+            vec![
+                Rect::new(
+                    point.x + inset as i32,
+                    point.y,
+                    pixel_size - 2 * inset,
+                    pixel_size,
+                ),
+                Rect::new(
+                    point.x,
+                    point.y + inset as i32,
+                    pixel_size,
+                    pixel_size - 2 * inset,
+                ),
+            ]
+        }
+    };
+    if let Err(e) = canvas.fill_rects(&rects) {
+        println!("{:?}", e);
+    }
+}
+
+// The audio device plays the frames the emulation queues, see chip8_sound
+struct Buzzer {
+    synthesizer: Synthesizer,
+}
+
+impl AudioCallback for Buzzer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.synthesizer.generate(out);
     }
 }
 
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     println!("Started rusty_chip!");
 
-    let mut chip8: Chip8 = Chip8::new();
-    let console_rom: Vec<u8> = get_file_as_byte_vec(&config.console_rom_filename);
-    chip8.init_memory(console_rom.iter().as_ref(), 0x0);
+    // A movie replays on a machine set up like the one it was recorded on
+    let movie: Option<Movie> = match &config.play_filename {
+        Some(play_filename) => {
+            let movie: Vec<u8> = fs::read(play_filename)
+                .map_err(|e| format!("Unable to read {}: {}", play_filename, e))?;
+            Some(Movie::from_bytes(&movie)?)
+        }
+        None => None,
+    };
+    let xo_chip: bool = movie.as_ref().map_or(config.xo_chip, |movie| movie.xo_chip);
+    let cartridge_rom: Rom = Rom::from_path(&config.cartridge_rom_filename, xo_chip)
+        .map_err(|e| format!("Unable to load {}: {}", config.cartridge_rom_filename, e))?;
+    println!(
+        "Loaded {}: {}",
+        config.cartridge_rom_filename,
+        cartridge_rom.describe()
+    );
+    if cartridge_rom.is_odd_length() {
+        eprintln!(
+            "{} has an odd length, it may have been cut short",
+            config.cartridge_rom_filename
+        );
+    }
+    let keymap: Keymap = load_keymap(
+        config.keymap_filename.as_deref(),
+        &config.cartridge_rom_filename,
+    )?;
+    let mut cycles_per_frame: usize = config.cycles_per_frame;
+    // A movie needs a known seed to be replayed
+    let seed: Option<u64> = match &config.record_filename {
+        Some(_) => Some(config.seed.unwrap_or_else(rand::random)),
+        None => config.seed,
+    };
 
-    let cartridge_rom: Vec<u8> = get_file_as_byte_vec(&config.cartridge_rom_filename);
-    chip8.init_memory(cartridge_rom.iter().as_ref(), 0x200);
+    let mut movie_player: Option<MoviePlayer> = None;
+    let mut chip8: Chip8 = match (movie, &config.play_filename) {
+        (Some(movie), Some(play_filename)) => {
+            let chip8: Chip8 = movie.create_machine(cartridge_rom.bytes())?;
+            cycles_per_frame = movie.cycles_per_frame;
+            println!(
+                "Playing {} frames from {}",
+                movie.frame_count, play_filename
+            );
+            movie_player = Some(MoviePlayer::new(movie));
+            chip8
+        }
+        _ => {
+            let mut chip8: Chip8 = if config.xo_chip {
+                Chip8::new_xo_chip(config.quirks)
+            } else {
+                Chip8::new(config.quirks)
+            };
+            if let Some(seed) = seed {
+                chip8.set_seed(seed);
+            }
+            chip8.load_rom(cartridge_rom.bytes())?;
+            chip8
+        }
+    };
+    if let Some(font_filename) = &config.font_filename {
+        let font: Font = fs::read(font_filename)
+            .map_err(|e| format!("Unable to read {}: {}", font_filename, e))?
+            .as_slice()
+            .try_into()
+            .map_err(|_| format!("Font {} must be {} bytes long", font_filename, FONT_SIZE))?;
+        chip8.set_font(&font);
+    }
+    let mut movie_recorder: Option<MovieRecorder> = match (&config.record_filename, seed) {
+        (Some(_), Some(seed)) => Some(MovieRecorder::new(
+            cartridge_rom.bytes(),
+            seed,
+            &chip8,
+            cycles_per_frame,
+        )),
+        _ => None,
+    };
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("Rusty Chip", 64 * PIXEL_SIZE, 32 * PIXEL_SIZE)
+        .window(
+            "Rusty Chip",
+            DISPLAY_WIDTH as u32 * PIXEL_SIZE,
+            DISPLAY_HEIGHT as u32 * PIXEL_SIZE,
+        )
         .position_centered()
         .build()
         .unwrap();
@@ -102,45 +626,63 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     let audio_subsystem = sdl_context.audio().unwrap();
 
     let desired_spec = AudioSpecDesired {
-        freq: Some(44100),
+        freq: Some(DEFAULT_SAMPLE_RATE as i32),
         channels: Some(1), // mono
-        samples: None,     // default sample size
+        // About 23 ms, so the buzzer follows the emulation closely
+        samples: Some(1024),
     };
 
-    let device = audio_subsystem
-        .open_playback(None, &desired_spec, |spec| {
-            // initialize the audio callback
-            SquareWave {
-                phase_inc: 440.0 / spec.freq as f32,
-                phase: 0.0,
-                volume: 0.25,
-            }
+    // The device always plays, the buzzer is silent between beeps
+    let mut device = audio_subsystem
+        .open_playback(None, &desired_spec, |spec| Buzzer {
+            synthesizer: Synthesizer::new(config.sound, spec.freq as u32),
         })
         .unwrap();
+    device.resume();
     let mut canvas = window.into_canvas().build().unwrap();
 
     let mut event_pump = sdl_context.event_pump().unwrap();
+    // Controllers already plugged in are announced by ControllerDeviceAdded events too
+    let controller_subsystem: GameControllerSubsystem = sdl_context.game_controller()?;
+    let mut controllers = Controllers { slots: Vec::new() };
+    let mut keypad: KeypadState = KeypadState::new();
+    // Keys pressed or released since the last frame, applied right before running one
+    let mut keypad_changes: Vec<(u8, bool)> = Vec::new();
+    // F11 cycles through the named palettes, Shift+F11 through the pixel styles
+    let mut palette: Palette = config.palette;
+    let mut palette_index: Option<usize> = PALETTE_NAMES
+        .iter()
+        .position(|name| Palette::from_name(name) == Some(palette));
+    let mut pixel_style: PixelStyle = config.pixel_style;
+    // Shift+F12 starts recording a GIF of the window size, and stops it
+    let mut gif_recorder: Option<GifRecorder> = None;
+
+    // Once the program crashes, the debugger pauses it and the last frame stays on screen
+    let mut debugger: Debugger = Debugger::new();
+    // The frame of the debugger the movie last recorded or played
+    let mut movie_frame: Option<u64> = None;
+    // Holding backspace plays the game backwards, one stored frame per frame
+    let mut rewind: Rewind = Rewind::new(config.rewind_memory);
+    let mut is_rewinding: bool = false;
+    let debug_prompt: Option<mpsc::Receiver<DebugCommand>> = if config.debug {
+        debugger.pause();
+        println!("{}", rusty_chip::chip8_debugger::DEBUG_HELP);
+        print_debug_output(&Debugger::dump(&chip8));
+        Some(spawn_debug_prompt())
+    } else {
+        None
+    };
 
-    chip8.key_states = 0;
+    let frame_duration: Duration = Duration::from_nanos(1_000_000_000 / FRAMES_PER_SECOND as u64);
+    let mut next_frame: Instant = Instant::now();
 
     'running: loop {
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        // Rewinding and loading states would break the recording or the playback
+        let is_movie_active: bool = movie_recorder.is_some() || movie_player.is_some();
+        canvas.set_draw_color(clear_color(&palette, pixel_style));
         canvas.clear();
 
-        let is_ticking: bool = chip8.wait_key_state & 0xF0 == 0xF0;
-        if is_ticking {
-            chip8.decrement_timers();
-        }
-
-        if chip8.is_sound_active() {
-            device.resume();
-        } else {
-            device.pause();
-        }
-
         for event in event_pump.poll_iter() {
-            // http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#2.3
-            // TODO: Take this logic out in another function, find a way to clean this
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
@@ -148,156 +690,244 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
                     ..
                 } => break 'running,
                 Event::KeyDown {
-                    keycode: Some(Keycode::Num0),
-                    ..
-                } => chip8.key_states |= 0x8000,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num1),
-                    ..
-                } => chip8.key_states |= 0x4000,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num2),
-                    ..
-                } => chip8.key_states |= 0x2000,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num3),
-                    ..
-                } => chip8.key_states |= 0x1000,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num4),
+                    keycode: Some(Keycode::F5),
                     ..
-                } => chip8.key_states |= 0x0800,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num5),
-                    ..
-                } => chip8.key_states |= 0x0400,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num6),
-                    ..
-                } => chip8.key_states |= 0x0200,
+                } if !debugger.is_paused() => {
+                    debugger.pause();
+                    print_debug_output(&Debugger::dump(&chip8));
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::Num7),
+                    keycode: Some(Keycode::Backspace),
                     ..
-                } => chip8.key_states |= 0x0100,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num8),
+                } => is_rewinding = config.rewind_memory > 0 && !is_movie_active,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
                     ..
-                } => chip8.key_states |= 0x0080,
+                } => is_rewinding = false,
                 Event::KeyDown {
-                    keycode: Some(Keycode::Num9),
+                    keycode: Some(Keycode::F11),
+                    keymod,
                     ..
-                } => chip8.key_states |= 0x0040,
+                } if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                    pixel_style = pixel_style.next();
+                    println!("Pixel style: {}", pixel_style.name());
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::A),
-                    ..
-                } => chip8.key_states |= 0x0020,
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => {
+                    let index: usize =
+                        palette_index.map_or(0, |index| index + 1) % PALETTE_NAMES.len();
+                    palette = Palette::from_name(PALETTE_NAMES[index]).unwrap_or_default();
+                    palette_index = Some(index);
+                    println!("Palette: {}", PALETTE_NAMES[index]);
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::B),
-                    ..
-                } => chip8.key_states |= 0x0010,
+                    keycode: Some(Keycode::F12),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                    match gif_recorder.take() {
+                        Some(recorder) => save_gif(recorder, &config.cartridge_rom_filename),
+                        None => {
+                            println!("Recording a GIF, Shift+F12 again to stop");
+                            // As big as the window
+                            gif_recorder =
+                                Some(GifRecorder::new(&palette, PIXEL_SIZE as usize / 2));
+                        }
+                    }
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::C),
+                    keycode: Some(Keycode::F12),
                     ..
-                } => chip8.key_states |= 0x0008,
+                } => save_screenshot(&chip8, &config.cartridge_rom_filename, &palette),
                 Event::KeyDown {
-                    keycode: Some(Keycode::D),
-                    ..
-                } => chip8.key_states |= 0x0004,
+                    keycode: Some(Keycode::M),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let mut buzzer = device.lock();
+                    let mut settings: SoundSettings = buzzer.synthesizer.settings();
+                    settings.is_muted = !settings.is_muted;
+                    buzzer.synthesizer.set_settings(settings);
+                    println!("Sound: {}", if settings.is_muted { "muted" } else { "on" });
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::E),
-                    ..
-                } => chip8.key_states |= 0x0002,
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => {
+                    let pc: u16 = chip8.program_counter();
+                    if debugger.toggle_breakpoint(pc) {
+                        print_debug_output(&format!("Breakpoint added at {:#05X}", pc));
+                    } else {
+                        print_debug_output(&format!("Breakpoint removed at {:#05X}", pc));
+                    }
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::F),
-                    ..
-                } => chip8.key_states |= 0x0001,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num0),
-                    ..
-                } => chip8.key_states &= !0x8000,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num1),
-                    ..
-                } => chip8.key_states &= !0x4000,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num2),
-                    ..
-                } => chip8.key_states &= !0x2000,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num3),
-                    ..
-                } => chip8.key_states &= !0x1000,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num4),
-                    ..
-                } => chip8.key_states &= !0x0800,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num5),
-                    ..
-                } => chip8.key_states &= !0x0400,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num6),
-                    ..
-                } => chip8.key_states &= !0x0200,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num7),
-                    ..
-                } => chip8.key_states &= !0x0100,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num8),
-                    ..
-                } => chip8.key_states &= !0x0080,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num9),
-                    ..
-                } => chip8.key_states &= !0x0040,
-                Event::KeyUp {
-                    keycode: Some(Keycode::A),
-                    ..
-                } => chip8.key_states &= !0x0020,
-                Event::KeyUp {
-                    keycode: Some(Keycode::B),
-                    ..
-                } => chip8.key_states &= !0x0010,
-                Event::KeyUp {
-                    keycode: Some(Keycode::C),
-                    ..
-                } => chip8.key_states &= !0x0008,
-                Event::KeyUp {
-                    keycode: Some(Keycode::D),
-                    ..
-                } => chip8.key_states &= !0x0004,
-                Event::KeyUp {
-                    keycode: Some(Keycode::E),
-                    ..
-                } => chip8.key_states &= !0x0002,
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } => {
+                    if keymap.key_for(&keycode.name()).is_some() {
+                        if movie_player.is_none() {
+                            let change = keypad.set(&keymap, &keycode.name(), true);
+                            keypad_changes.extend(change);
+                        }
+                    } else if let Some(slot) = keycode_to_save_slot(keycode) {
+                        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            save_to_slot(&chip8, &config.cartridge_rom_filename, slot);
+                        } else if is_movie_active {
+                            eprintln!("Save states cannot be loaded during a movie");
+                        } else {
+                            load_from_slot(&mut chip8, &config.cartridge_rom_filename, slot);
+                        }
+                    } else if let Some(command) = keycode_to_debug_command(keycode) {
+                        print_debug_output(&debugger.execute(command, &chip8));
+                    }
+                }
+                // The keypad is driven by the movie during playback
                 Event::KeyUp {
-                    keycode: Some(Keycode::F),
+                    keycode: Some(keycode),
                     ..
-                } => chip8.key_states &= !0x0001,
+                } if movie_player.is_none() => {
+                    let change = keypad.set(&keymap, &keycode.name(), false);
+                    keypad_changes.extend(change);
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match controllers.add(&controller_subsystem, which) {
+                        Ok(player) => println!("Controller connected as player {}", player),
+                        Err(e) => eprintln!("Unable to open controller {}: {}", which, e),
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    if let Some(player) = controllers.remove(which) {
+                        println!("Controller of player {} disconnected", player);
+                        let changes = keypad.release_all(&keymap, &player_prefix(player));
+                        if movie_player.is_none() {
+                            keypad_changes.extend(changes);
+                        }
+                    }
+                }
+                Event::ControllerButtonDown { .. }
+                | Event::ControllerButtonUp { .. }
+                | Event::ControllerAxisMotion { .. }
+                    if movie_player.is_none() =>
+                {
+                    for (name, is_down) in controller_inputs(&event, &controllers) {
+                        let change = keypad.set(&keymap, &name, is_down);
+                        keypad_changes.extend(change);
+                    }
+                }
                 _ => {}
             }
         }
 
-        if !is_ticking {
-            for i in 0..16 {
-                if (chip8.key_states >> (15 - i)) & 1 == 1 {
-                    chip8.cpu_registers[chip8.wait_key_state as usize] = i as u8;
-                    chip8.wait_key_state = 0xF0;
-                }
+        if let Some(prompt) = &debug_prompt {
+            for command in prompt.try_iter() {
+                print_debug_output(&debugger.execute(command, &chip8));
             }
         }
 
-        if is_ticking {
-            chip8.fetch_cycle();
+        // Catch up on every frame that is due since the last iteration
+        let mut frames_run: u32 = 0;
+        while next_frame <= Instant::now() {
+            if frames_run == MAX_FRAMES_BEHIND {
+                next_frame = Instant::now();
+                break;
+            }
+
+            if is_rewinding {
+                rewind.rewind(&mut chip8);
+                device.lock().synthesizer.push_frame(FrameSound::silent());
+                if let Some(recorder) = &mut gif_recorder {
+                    recorder.capture(&chip8);
+                }
+                next_frame += frame_duration;
+                frames_run += 1;
+                continue;
+            }
+
+            // The debugger may stop in the middle of a frame or before its first instruction.
+            // Movies hold the keys and checkpoints of whole frames, so they are taken once
+            // per frame before it starts, and key changes wait for the next one.
+            let is_new_frame: bool = movie_frame != Some(debugger.frame_count());
+            if movie_recorder.is_none() || is_new_frame {
+                for change in keypad_changes.drain(..) {
+                    apply_keypad_change(&mut chip8, change)?;
+                }
+            }
+            let is_running: bool = !debugger.is_paused();
+            if is_running && is_new_frame {
+                movie_frame = Some(debugger.frame_count());
+                if let Some(recorder) = &mut movie_recorder {
+                    recorder.record_frame(&chip8);
+                }
+                if let Some(player) = &mut movie_player {
+                    // Once the movie ends or desyncs, the keypad is handed back to the user
+                    if let Err(e) = player.play_frame(&mut chip8) {
+                        eprintln!("{}", e);
+                        movie_player = None;
+                    } else if player.is_finished() {
+                        println!("The movie is over");
+                        movie_player = None;
+                    }
+                }
+            }
+            if let Some(stop) = debugger.run_frame(&mut chip8, cycles_per_frame) {
+                if let DebugStop::Error(e) = &stop {
+                    eprintln!("Emulation stopped: {}", e);
+                    canvas
+                        .window_mut()
+                        .set_title(&format!("Rusty Chip - {}", e))?;
+                }
+                print_debug_output(&Debugger::describe_stop(&stop, &chip8));
+            }
+            if is_running && config.rewind_memory > 0 {
+                rewind.push(&chip8);
+            }
+            // A paused machine is silent
+            let sound: FrameSound = if is_running && !debugger.is_paused() {
+                FrameSound::from_chip8(&chip8)
+            } else {
+                FrameSound::silent()
+            };
+            device.lock().synthesizer.push_frame(sound);
+            if let Some(recorder) = &mut gif_recorder {
+                recorder.capture(&chip8);
+            }
+            next_frame += frame_duration;
+            frames_run += 1;
         }
 
-        for (index, alpha) in chip8.gfx.iter().enumerate() {
-            set_grid_index_color(&mut canvas, index as i32, *alpha);
+        for (index, planes) in chip8.framebuffer().iter().enumerate() {
+            set_grid_index_color(
+                &mut canvas,
+                index as i32,
+                *planes,
+                chip8.display_width(),
+                &palette,
+                pixel_style,
+            );
         }
 
         canvas.present();
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 1000));
+
+        if chip8.has_exited() {
+            break 'running;
+        }
+        ::std::thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+    }
+
+    if let Some(recorder) = gif_recorder {
+        save_gif(recorder, &config.cartridge_rom_filename);
+    }
+    if let (Some(recorder), Some(record_filename)) = (movie_recorder, &config.record_filename) {
+        let movie: Movie = recorder.finish();
+        fs::write(record_filename, movie.to_bytes())?;
+        println!(
+            "Recorded {} frames to {}",
+            movie.frame_count, record_filename
+        );
     }
     Ok(())
 }
@@ -0,0 +1,365 @@
+use crate::chip8::{Chip8, AUDIO_PATTERN_SIZE, FRAMES_PER_SECOND};
+use crate::chip8_rng::Rng;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+// Waveforms that can be picked by name
+pub const WAVEFORM_NAMES: [&str; 4] = ["square", "triangle", "sine", "noise"];
+pub const DEFAULT_FREQUENCY: f32 = 440.0;
+pub const DEFAULT_VOLUME: f32 = 0.25;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+// The envelope takes this long to rise or fall, so the buzzer never clicks
+const FADE_SECONDS: f32 = 0.005;
+// Frames waiting to be played. A frontend running ahead of the audio device loses its
+// oldest frames rather than lagging further behind.
+const MAX_QUEUED_FRAMES: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sine,
+    // A new random level every half period
+    Noise,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sine" => Some(Waveform::Sine),
+            "noise" => Some(Waveform::Noise),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoundSettings {
+    // Pitch of the buzzer in Hz. XO-CHIP audio patterns play at their own pitch.
+    pub frequency: f32,
+    pub waveform: Waveform,
+    // From 0 to 1
+    pub volume: f32,
+    pub is_muted: bool,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        SoundSettings {
+            frequency: DEFAULT_FREQUENCY,
+            waveform: Waveform::Square,
+            volume: DEFAULT_VOLUME,
+            is_muted: false,
+        }
+    }
+}
+
+// What the buzzer does during one frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameSound {
+    pub is_active: bool,
+    // The XO-CHIP audio pattern replaces the waveform once a program loads one
+    pub pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    // Bits of the pattern played per second
+    pub pattern_rate: f32,
+}
+
+impl FrameSound {
+    pub fn silent() -> FrameSound {
+        FrameSound {
+            is_active: false,
+            pattern: None,
+            pattern_rate: 0.0,
+        }
+    }
+
+    // The sound of the frame the machine just ran
+    pub fn from_chip8(chip8: &Chip8) -> FrameSound {
+        FrameSound {
+            is_active: chip8.has_sounded(),
+            pattern: chip8.audio_pattern().copied(),
+            pattern_rate: chip8.audio_pattern_rate(),
+        }
+    }
+}
+
+// Turns the frames of the machine into samples, one frame lasting exactly 1/60 s of samples.
+// Frames are queued by the emulation and played in order by the audio device, so the buzzer
+// starts and stops on the sample a frame starts at, whenever the device asks for samples.
+pub struct Synthesizer {
+    settings: SoundSettings,
+    sample_rate: u32,
+    frames: VecDeque<FrameSound>,
+    // The frame being played, it keeps playing when no other frame is queued
+    current: FrameSound,
+    samples_left: u32,
+    // Position in the period of the waveform, from 0 to 1
+    phase: f32,
+    // Position in the pattern, in bits
+    pattern_phase: f32,
+    noise: Rng,
+    noise_level: f32,
+    // From 0, silent, to 1
+    envelope: f32,
+}
+
+impl Synthesizer {
+    pub fn new(settings: SoundSettings, sample_rate: u32) -> Synthesizer {
+        Synthesizer {
+            settings,
+            sample_rate,
+            frames: VecDeque::new(),
+            current: FrameSound::silent(),
+            samples_left: 0,
+            phase: 0.0,
+            pattern_phase: 0.0,
+            noise: Rng::new(0),
+            noise_level: 1.0,
+            envelope: 0.0,
+        }
+    }
+
+    pub fn settings(&self) -> SoundSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: SoundSettings) {
+        self.settings = settings;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // Samples in a frame, 735 at 44.1 kHz
+    pub fn samples_per_frame(&self) -> u32 {
+        self.sample_rate / FRAMES_PER_SECOND
+    }
+
+    pub fn push_frame(&mut self, frame: FrameSound) {
+        if self.frames.len() >= MAX_QUEUED_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    pub fn generate(&mut self, out: &mut [f32]) {
+        let fade_step: f32 = 1.0 / (FADE_SECONDS * self.sample_rate as f32);
+        for sample in out.iter_mut() {
+            if self.samples_left == 0 {
+                if let Some(frame) = self.frames.pop_front() {
+                    self.current = frame;
+                }
+                self.samples_left = self.samples_per_frame();
+            }
+            self.samples_left -= 1;
+
+            let target: f32 = if self.current.is_active && !self.settings.is_muted {
+                1.0
+            } else {
+                0.0
+            };
+            if self.envelope < target {
+                self.envelope = (self.envelope + fade_step).min(target);
+            } else {
+                self.envelope = (self.envelope - fade_step).max(target);
+            }
+
+            let level: f32 = self.next_level();
+            *sample = level * self.settings.volume * self.envelope;
+        }
+    }
+
+    // The waveform, or the pattern, from -1 to 1, advanced by one sample
+    fn next_level(&mut self) -> f32 {
+        let sample_rate: f32 = self.sample_rate as f32;
+        if let Some(pattern) = &self.current.pattern {
+            // Play the pattern one bit after the other, 1 is high and 0 is low
+            let pattern_bits: f32 = (AUDIO_PATTERN_SIZE * 8) as f32;
+            let bit: usize = self.pattern_phase as usize;
+            self.pattern_phase =
+                (self.pattern_phase + self.current.pattern_rate / sample_rate) % pattern_bits;
+            return if (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1 {
+                1.0
+            } else {
+                -1.0
+            };
+        }
+
+        let phase: f32 = self.phase;
+        self.phase = (self.phase + self.settings.frequency / sample_rate) % 1.0;
+        if self.settings.waveform == Waveform::Noise && (phase < 0.5) != (self.phase < 0.5) {
+            self.noise_level = if self.noise.next_u8() & 1 == 1 {
+                1.0
+            } else {
+                -1.0
+            };
+        }
+        waveform_level(self.settings.waveform, phase, self.noise_level)
+    }
+}
+
+// Renders the sound of every frame a machine runs, for a WAV file when there is no audio
+// device. Call capture once per frame, after the frame ran.
+pub struct WavRecorder {
+    synthesizer: Synthesizer,
+    samples: Vec<f32>,
+}
+
+impl WavRecorder {
+    pub fn new(settings: SoundSettings, sample_rate: u32) -> WavRecorder {
+        WavRecorder {
+            synthesizer: Synthesizer::new(settings, sample_rate),
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn capture(&mut self, chip8: &Chip8) {
+        self.synthesizer.push_frame(FrameSound::from_chip8(chip8));
+        let start: usize = self.samples.len();
+        let samples_per_frame: usize = self.synthesizer.samples_per_frame() as usize;
+        self.samples.resize(start + samples_per_frame, 0.0);
+        self.synthesizer.generate(&mut self.samples[start..]);
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        encode_wav(self.synthesizer.sample_rate(), &self.samples)
+    }
+}
+
+// 16 bit mono PCM
+pub fn encode_wav(sample_rate: u32, samples: &[f32]) -> Vec<u8> {
+    let data_size: u32 = samples.len() as u32 * 2;
+    let mut wav: Vec<u8> = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    // Bytes per second and per sample, then bits per sample
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        let value: i16 = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&value.to_le_bytes());
+    }
+    wav
+}
+
+fn waveform_level(waveform: Waveform, phase: f32, noise_level: f32) -> f32 {
+    match waveform {
+        Waveform::Square if phase < 0.5 => 1.0,
+        Waveform::Square => -1.0,
+        Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        Waveform::Sine => (2.0 * PI * phase).sin(),
+        Waveform::Noise => noise_level,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beep(is_active: bool) -> FrameSound {
+        FrameSound {
+            is_active,
+            ..FrameSound::silent()
+        }
+    }
+
+    #[test]
+    pub fn waveform_level_test() {
+        assert_eq!(waveform_level(Waveform::Square, 0.25, 0.0), 1.0);
+        assert_eq!(waveform_level(Waveform::Square, 0.75, 0.0), -1.0);
+        assert_eq!(waveform_level(Waveform::Triangle, 0.0, 0.0), -1.0);
+        assert_eq!(waveform_level(Waveform::Triangle, 0.5, 0.0), 1.0);
+        assert_eq!(waveform_level(Waveform::Triangle, 0.75, 0.0), 0.0);
+        assert!((waveform_level(Waveform::Sine, 0.25, 0.0) - 1.0).abs() < 1e-6);
+        assert_eq!(waveform_level(Waveform::Noise, 0.3, -1.0), -1.0);
+        for name in WAVEFORM_NAMES.iter() {
+            assert!(Waveform::from_name(name).is_some());
+        }
+    }
+
+    #[test]
+    pub fn gating_test() {
+        let mut synthesizer = Synthesizer::new(SoundSettings::default(), 44100);
+        assert_eq!(synthesizer.samples_per_frame(), 735);
+        synthesizer.push_frame(beep(false));
+        synthesizer.push_frame(beep(true));
+        synthesizer.push_frame(beep(false));
+        // Asked for in chunks that do not line up with the frames
+        let mut samples: Vec<f32> = vec![0.0; 735 * 3];
+        for chunk in samples.chunks_mut(512) {
+            synthesizer.generate(chunk);
+        }
+
+        // Silent until the second frame, on the very sample it starts at
+        assert!(samples[..735].iter().all(|sample| *sample == 0.0));
+        assert_ne!(samples[735], 0.0);
+        // Fades in over 5 ms, then plays at full volume
+        assert!(samples[735].abs() < 0.01);
+        assert_eq!(samples[735 + 300].abs(), DEFAULT_VOLUME);
+        // And fades out once the third frame starts
+        assert!(samples[735 * 2 + 100].abs() < DEFAULT_VOLUME);
+        assert!(samples[735 * 2 + 300..].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    pub fn wav_recorder_test() {
+        let mut c: Chip8 = Chip8::new(crate::Quirks::default());
+        // Sets the sound timer to 3, then loops
+        c.load_rom(&[0x60, 0x03, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+        let mut recorder = WavRecorder::new(SoundSettings::default(), DEFAULT_SAMPLE_RATE);
+        for _ in 0..6 {
+            c.run_frame(2).unwrap();
+            recorder.capture(&c);
+        }
+
+        let samples: &[f32] = recorder.samples();
+        assert_eq!(samples.len(), 6 * 735);
+        // The beep lasts the 3 frames, then fades out over 5 ms
+        assert_eq!(samples[0..735 * 3].iter().filter(|s| **s == 0.0).count(), 0);
+        assert!(samples[735 * 3 + 221..].iter().all(|sample| *sample == 0.0));
+
+        let wav: Vec<u8> = recorder.finish();
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(wav[24..28], 44100u32.to_le_bytes());
+        assert_eq!(wav[40..44], (6u32 * 735 * 2).to_le_bytes());
+        assert_eq!(wav.len(), 44 + 6 * 735 * 2);
+    }
+
+    #[test]
+    pub fn mute_test() {
+        let settings = SoundSettings {
+            is_muted: true,
+            ..SoundSettings::default()
+        };
+        let mut synthesizer = Synthesizer::new(settings, 48000);
+        synthesizer.push_frame(beep(true));
+        let mut samples: Vec<f32> = vec![1.0; 800];
+        synthesizer.generate(&mut samples);
+        assert!(samples.iter().all(|sample| *sample == 0.0));
+
+        // The last frame keeps playing until the next one is queued
+        synthesizer.set_settings(SoundSettings::default());
+        synthesizer.generate(&mut samples);
+        assert_eq!(samples[799].abs(), DEFAULT_VOLUME);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::chip8_error::Chip8Error;
+use crate::chip8_util::crc32;
+
+// Layout of a save state, integers are little endian:
+// magic (8 bytes) | version (u16) | payload length (u32) | payload CRC-32 (u32) | payload
+// The payload is written by Chip8::save_state, field after field.
+pub const SAVE_STATE_MAGIC: &[u8; 8] = b"RCHIPSAV";
+pub const SAVE_STATE_VERSION: u16 = 1;
+pub(crate) const HEADER_SIZE: usize = 18;
+
+// Builds the payload of a save state
+pub(crate) struct StateWriter {
+    payload: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter {
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.payload.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.payload.extend_from_slice(bytes);
+    }
+
+    // Prepends the header to the payload
+    pub fn finish(self) -> Vec<u8> {
+        self.finish_with_header(SAVE_STATE_MAGIC, SAVE_STATE_VERSION)
+    }
+
+    // Same layout as a save state, for other files made of a payload and a header
+    pub fn finish_with_header(self, magic: &[u8; 8], version: u16) -> Vec<u8> {
+        let mut state: Vec<u8> = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        state.extend_from_slice(magic);
+        state.extend_from_slice(&version.to_le_bytes());
+        state.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        state.extend_from_slice(&crc32(&self.payload).to_le_bytes());
+        state.extend_from_slice(&self.payload);
+        state
+    }
+}
+
+fn invalid(reason: &'static str) -> Chip8Error {
+    Chip8Error::InvalidSaveState { reason }
+}
+
+// Reads back the payload of a save state, after checking its header
+pub(crate) struct StateReader<'a> {
+    payload: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn open(state: &'a [u8]) -> Result<StateReader<'a>, Chip8Error> {
+        StateReader::open_with_header(state, SAVE_STATE_MAGIC, SAVE_STATE_VERSION)
+    }
+
+    // Reads a file written by StateWriter::finish_with_header
+    pub fn open_with_header(
+        state: &'a [u8],
+        magic: &[u8; 8],
+        version: u16,
+    ) -> Result<StateReader<'a>, Chip8Error> {
+        if state.len() < HEADER_SIZE || &state[0..8] != magic {
+            return Err(invalid("not a save state"));
+        }
+        if u16::from_le_bytes([state[8], state[9]]) != version {
+            return Err(invalid("unsupported version"));
+        }
+        let length: usize =
+            u32::from_le_bytes([state[10], state[11], state[12], state[13]]) as usize;
+        let checksum: u32 = u32::from_le_bytes([state[14], state[15], state[16], state[17]]);
+        let payload: &[u8] = &state[HEADER_SIZE..];
+        if payload.len() != length {
+            return Err(invalid("truncated"));
+        }
+        if crc32(payload) != checksum {
+            return Err(invalid("checksum mismatch"));
+        }
+        Ok(StateReader {
+            payload,
+            position: 0,
+        })
+    }
+
+    pub fn bytes(&mut self, count: usize) -> Result<&'a [u8], Chip8Error> {
+        let end: usize = self.position + count;
+        if end > self.payload.len() {
+            return Err(invalid("truncated"));
+        }
+        let bytes: &'a [u8] = &self.payload[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Chip8Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("corrupted")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Chip8Error> {
+        let bytes: &[u8] = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Chip8Error> {
+        let bytes: &[u8] = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Chip8Error> {
+        let mut bytes: [u8; 8] = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    // Fails if the payload has leftover bytes
+    pub fn finish(self) -> Result<(), Chip8Error> {
+        if self.position != self.payload.len() {
+            return Err(invalid("corrupted"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn round_trip_test() {
+        let mut writer: StateWriter = StateWriter::new();
+        writer.u8(0x12);
+        writer.bool(true);
+        writer.u16(0x3456);
+        writer.u32(0x789A_BCDE);
+        writer.u64(0x0123_4567_89AB_CDEF);
+        writer.bytes(&[1, 2, 3]);
+        let state: Vec<u8> = writer.finish();
+
+        let mut reader: StateReader = StateReader::open(&state).unwrap();
+        assert_eq!(reader.u8(), Ok(0x12));
+        assert_eq!(reader.bool(), Ok(true));
+        assert_eq!(reader.u16(), Ok(0x3456));
+        assert_eq!(reader.u32(), Ok(0x789A_BCDE));
+        assert_eq!(reader.u64(), Ok(0x0123_4567_89AB_CDEF));
+        assert_eq!(reader.bytes(3), Ok(&[1, 2, 3][..]));
+        assert!(reader.u8().is_err());
+        assert!(reader.finish().is_ok());
+    }
+
+    #[test]
+    pub fn corrupted_state_test() {
+        let mut writer: StateWriter = StateWriter::new();
+        writer.u32(42);
+        let state: Vec<u8> = writer.finish();
+
+        let mut flipped: Vec<u8> = state.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            StateReader::open(&flipped).err(),
+            Some(invalid("checksum mismatch"))
+        );
+        assert_eq!(
+            StateReader::open(&state[..state.len() - 1]).err(),
+            Some(invalid("truncated"))
+        );
+        assert_eq!(
+            StateReader::open(b"not a state at all").err(),
+            Some(invalid("not a save state"))
+        );
+
+        let mut newer: Vec<u8> = state;
+        newer[8] = 99;
+        assert_eq!(
+            StateReader::open(&newer).err(),
+            Some(invalid("unsupported version"))
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use rusty_chip::chip8::{Chip8, DEFAULT_CYCLES_PER_FRAME, FRAMES_PER_SECOND, KEY_COUNT};
+use rusty_chip::chip8_keymap::{load_keymap, Keymap};
+use rusty_chip::chip8_palette::{Palette, Rgb};
+use rusty_chip::chip8_rom::Rom;
+use rusty_chip::Quirks;
+use std::error::Error;
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+// Most terminals only report key presses and their auto repeats, never releases.
+// There, a key stays held until this many frames pass without it being repeated.
+const KEY_HOLD_FRAMES: u32 = 10;
+// Draws two pixels in one character: the upper one in the foreground colour and the lower
+// one in the background colour
+const UPPER_HALF_BLOCK: char = '\u{2580}';
+const SOUND_INDICATOR: &str = "\u{266A} BEEP";
+
+pub struct Config {
+    pub cartridge_rom_filename: String,
+    pub quirks: Quirks,
+    pub cycles_per_frame: usize,
+    pub xo_chip: bool,
+    pub seed: Option<u64>,
+    // Rings the terminal bell when the buzzer starts, instead of only showing it on screen
+    pub bell: bool,
+    // Binds the keyboard to the hex keypad, see chip8_keymap for the format
+    pub keymap_filename: Option<String>,
+    pub palette: Palette,
+}
+
+impl Config {
+    // rusty-chip term <rom> [options]
+    pub fn new(args: &[String]) -> Result<Config, &'static str> {
+        if args.len() < 3 {
+            return Err("term requires a ROM filename");
+        }
+
+        let cartridge_rom_filename = args[2].clone();
+        let mut quirks: Option<Quirks> = None;
+        let mut cycles_per_frame: usize = DEFAULT_CYCLES_PER_FRAME;
+        let mut xo_chip: bool = false;
+        let mut seed: Option<u64> = None;
+        let mut bell: bool = false;
+        let mut keymap_filename: Option<String> = None;
+        let mut palette: Palette = Palette::default();
+
+        let mut options = args[3..].iter();
+        while let Some(option) = options.next() {
+            match option.as_str() {
+                "--quirks" => match options.next().and_then(|name| Quirks::from_name(name)) {
+                    Some(profile) => quirks = Some(profile),
+                    None => {
+                        return Err("--quirks requires one of vip, chip48, schip, xochip or modern")
+                    }
+                },
+                "--cycles-per-frame" => match options.next().and_then(|n| n.parse().ok()) {
+                    Some(cycles) if cycles > 0 => cycles_per_frame = cycles,
+                    _ => return Err("--cycles-per-frame requires a positive number"),
+                },
+                "--xo-chip" => xo_chip = true,
+                "--seed" => match options.next().and_then(|n| n.parse().ok()) {
+                    Some(number) => seed = Some(number),
+                    None => return Err("--seed requires a number"),
+                },
+                "--bell" => bell = true,
+                "--palette" => match options.next().and_then(|p| Palette::parse(p)) {
+                    Some(colors) => palette = colors,
+                    None => return Err("--palette requires a palette name or #RRGGBB,#RRGGBB"),
+                },
+                "--keymap" => match options.next() {
+                    Some(filename) => keymap_filename = Some(filename.clone()),
+                    None => return Err("--keymap requires a filename"),
+                },
+                _ => return Err("unknown option"),
+            }
+        }
+
+        // XO-CHIP programs expect the quirks of Octo unless told otherwise
+        let quirks: Quirks = quirks.unwrap_or_else(|| {
+            if xo_chip {
+                Quirks::xo_chip()
+            } else {
+                Quirks::default()
+            }
+        });
+
+        Ok(Config {
+            cartridge_rom_filename,
+            quirks,
+            cycles_per_frame,
+            xo_chip,
+            seed,
+            bell,
+            keymap_filename,
+            palette,
+        })
+    }
+}
+
+// Colours of the two pixels shown by one character
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    top: Rgb,
+    bottom: Rgb,
+}
+
+// The display as rows of cells, each cell covering two rows of pixels
+fn render_cells(chip8: &Chip8, palette: &Palette) -> Vec<Cell> {
+    let width: usize = chip8.display_width();
+    chip8
+        .framebuffer()
+        .chunks(width * 2)
+        .flat_map(|rows| {
+            let (top, bottom) = rows.split_at(width);
+            top.iter().zip(bottom).map(move |(top, bottom)| Cell {
+                top: palette.color(*top),
+                bottom: palette.color(*bottom),
+            })
+        })
+        .collect()
+}
+
+// Indexes of the cells to redraw, all of them when the resolution changed
+fn changed_cells(previous: &[Cell], current: &[Cell]) -> Vec<usize> {
+    if previous.len() != current.len() {
+        return (0..current.len()).collect();
+    }
+    (0..current.len())
+        .filter(|index| previous[*index] != current[*index])
+        .collect()
+}
+
+// Names the keys the way SDL does, so one keymap file serves both frontends
+fn key_name(code: KeyCode) -> Option<String> {
+    match code {
+        KeyCode::Char(' ') => Some(String::from("Space")),
+        KeyCode::Char(c) => Some(c.to_string()),
+        KeyCode::Up => Some(String::from("Up")),
+        KeyCode::Down => Some(String::from("Down")),
+        KeyCode::Left => Some(String::from("Left")),
+        KeyCode::Right => Some(String::from("Right")),
+        KeyCode::Enter => Some(String::from("Return")),
+        KeyCode::Tab => Some(String::from("Tab")),
+        _ => None,
+    }
+}
+
+// Puts the terminal in raw mode on an alternate screen, and restores it when dropped,
+// even when the emulation stops on an error.
+struct TerminalGuard {
+    has_release_events: bool,
+}
+
+impl TerminalGuard {
+    fn enter(stdout: &mut Stdout) -> io::Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+        // Terminals implementing the kitty keyboard protocol report releases
+        let has_release_events: bool = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if has_release_events {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(TerminalGuard { has_release_events })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout: Stdout = io::stdout();
+        if self.has_release_events {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, ResetColor, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn terminal_color([r, g, b]: Rgb) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+fn draw(stdout: &mut Stdout, chip8: &Chip8, previous: &[Cell], cells: &[Cell]) -> io::Result<()> {
+    let width: usize = chip8.display_width();
+    let mut cursor_index: Option<usize> = None;
+    for index in changed_cells(previous, cells) {
+        // Consecutive cells are printed without moving the cursor
+        if cursor_index != Some(index) || index % width == 0 {
+            queue!(
+                stdout,
+                cursor::MoveTo((index % width) as u16, (index / width) as u16)
+            )?;
+        }
+        let cell: Cell = cells[index];
+        queue!(
+            stdout,
+            SetForegroundColor(terminal_color(cell.top)),
+            SetBackgroundColor(terminal_color(cell.bottom)),
+            Print(UPPER_HALF_BLOCK)
+        )?;
+        cursor_index = Some(index + 1);
+    }
+    Ok(())
+}
+
+fn draw_status(stdout: &mut Stdout, chip8: &Chip8) -> io::Result<()> {
+    let status: &str = if chip8.is_sound_active() {
+        SOUND_INDICATOR
+    } else {
+        ""
+    };
+    queue!(
+        stdout,
+        cursor::MoveTo(0, (chip8.display_height() / 2) as u16),
+        ResetColor,
+        terminal::Clear(ClearType::CurrentLine),
+        Print(status)
+    )
+}
+
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let cartridge_rom: Rom = Rom::from_path(&config.cartridge_rom_filename, config.xo_chip)
+        .map_err(|e| format!("Unable to load {}: {}", config.cartridge_rom_filename, e))?;
+    let mut chip8: Chip8 = if config.xo_chip {
+        Chip8::new_xo_chip(config.quirks)
+    } else {
+        Chip8::new(config.quirks)
+    };
+    if let Some(seed) = config.seed {
+        chip8.set_seed(seed);
+    }
+    chip8.load_rom(cartridge_rom.bytes())?;
+    let keymap: Keymap = load_keymap(
+        config.keymap_filename.as_deref(),
+        &config.cartridge_rom_filename,
+    )?;
+
+    let mut stdout: Stdout = io::stdout();
+    let guard: TerminalGuard = TerminalGuard::enter(&mut stdout)?;
+    // Frames left before each key is released, when the terminal has no release events
+    let mut key_hold_frames: [u32; KEY_COUNT as usize] = [0; KEY_COUNT as usize];
+    let mut previous_cells: Vec<Cell> = Vec::new();
+    let mut was_sound_active: bool = false;
+
+    let frame_duration: Duration = Duration::from_nanos(1_000_000_000 / FRAMES_PER_SECOND as u64);
+    let mut next_frame: Instant = Instant::now();
+
+    'running: loop {
+        while event::poll(Duration::ZERO)? {
+            match event::read()? {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                }) => break 'running,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => break 'running,
+                Event::Key(KeyEvent { code, kind, .. }) => {
+                    if let Some(key) = key_name(code).and_then(|name| keymap.key_for(&name)) {
+                        if kind == KeyEventKind::Release {
+                            chip8.release_key(key)?;
+                        } else {
+                            chip8.press_key(key)?;
+                            if !guard.has_release_events {
+                                key_hold_frames[key as usize] = KEY_HOLD_FRAMES;
+                            }
+                        }
+                    }
+                }
+                Event::Resize(..) => {
+                    // Redraw everything on the cleared screen
+                    execute!(stdout, terminal::Clear(ClearType::All))?;
+                    previous_cells.clear();
+                }
+                _ => {}
+            }
+        }
+
+        chip8.run_frame(config.cycles_per_frame)?;
+
+        for (key, frames) in key_hold_frames.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    chip8.release_key(key as u8)?;
+                }
+            }
+        }
+
+        let cells: Vec<Cell> = render_cells(&chip8, &config.palette);
+        // Switching resolution changes the size of the display on screen
+        let is_full_redraw: bool = cells.len() != previous_cells.len();
+        if is_full_redraw {
+            queue!(stdout, terminal::Clear(ClearType::All))?;
+        }
+        draw(&mut stdout, &chip8, &previous_cells, &cells)?;
+        let is_sound_active: bool = chip8.is_sound_active();
+        if is_sound_active != was_sound_active || is_full_redraw {
+            draw_status(&mut stdout, &chip8)?;
+            if is_sound_active && config.bell {
+                queue!(stdout, Print('\u{7}'))?;
+            }
+        }
+        stdout.flush()?;
+        previous_cells = cells;
+        was_sound_active = is_sound_active;
+
+        if chip8.has_exited() {
+            break 'running;
+        }
+        next_frame += frame_duration;
+        let now: Instant = Instant::now();
+        if next_frame < now {
+            next_frame = now;
+        }
+        std::thread::sleep(next_frame - now);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn render_cells_test() {
+        let mut c: Chip8 = Chip8::new(Quirks::default());
+        // Draws the 1 of the font in the top left corner
+        c.load_rom(&[0x60, 0x01, 0xF0, 0x29, 0xD1, 0x15]).unwrap();
+        let blank: Vec<Cell> = render_cells(&c, &Palette::default());
+        assert_eq!(blank.len(), 64 * 16);
+        c.run_frame(3).unwrap();
+        let cells: Vec<Cell> = render_cells(&c, &Palette::default());
+
+        // ..#. over .##. then ..#. over ..#. then .###
+        let lit = |top: bool, bottom: bool| Cell {
+            top: if top { [0xFF; 3] } else { [0x00; 3] },
+            bottom: if bottom { [0xFF; 3] } else { [0x00; 3] },
+        };
+        assert_eq!(
+            cells[0..3],
+            [lit(false, false), lit(false, true), lit(true, true)]
+        );
+        assert_eq!(cells[64 + 2], lit(true, true));
+        assert_eq!(cells[128 + 1], lit(true, false));
+        assert_eq!(changed_cells(&blank, &cells), [1, 2, 66, 129, 130, 131]);
+        assert_eq!(changed_cells(&[], &cells).len(), cells.len());
+    }
+
+    #[test]
+    pub fn key_name_test() {
+        let keymap = Keymap::default();
+        let key = |code: KeyCode| key_name(code).and_then(|name| keymap.key_for(&name));
+        assert_eq!(key(KeyCode::Char('q')), Some(0x4));
+        assert_eq!(key(KeyCode::Char('V')), Some(0xF));
+        assert_eq!(key(KeyCode::Char('5')), None);
+        assert_eq!(key_name(KeyCode::Char(' ')), Some(String::from("Space")));
+    }
+}
@@ -1,11 +1,87 @@
+use crate::chip8_error::Chip8Error;
 use num::PrimInt;
-use std::fmt::Display;
 
-pub fn validate_argument<T: PrimInt + Display>(value : T, mask: T) -> T {
+pub fn validate_argument<T: PrimInt>(value: T, mask: T) -> Result<T, Chip8Error> {
     if value.bitand(mask) != value {
-        panic!("Argument {} is outside of mask {}!", value, mask);
+        return Err(Chip8Error::ArgumentOutOfRange {
+            value: value.to_u32().unwrap_or(u32::MAX),
+            mask: mask.to_u32().unwrap_or(u32::MAX),
+        });
     }
-    value
+    Ok(value)
+}
+
+// CRC-32 as used by zip and PNG, reflected with the polynomial 0xEDB88320
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask: u32 = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// https://www.rfc-editor.org/rfc/rfc3174, the hash ROM databases list programs by
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+    // The data, a 1 bit, zeros up to 8 bytes before a 64 byte boundary, then its length in bits
+    let mut message: Vec<u8> = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words: [u32; 80] = [0; 80];
+        for (index, word) in block.chunks(4).enumerate() {
+            words[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..80 {
+            words[index] =
+                (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16])
+                    .rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (index, word) in words.iter().enumerate() {
+            let (f, k): (u32, u32) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp: u32 = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, added) in state.iter_mut().zip([a, b, c, d, e].iter()) {
+            *value = value.wrapping_add(*added);
+        }
+    }
+
+    let mut hash: [u8; 20] = [0; 20];
+    for (bytes, value) in hash.chunks_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    hash
 }
 
 #[cfg(test)]
@@ -14,22 +90,51 @@ mod tests {
 
     #[test]
     pub fn validate_argument_test() {
-        validate_argument(0x54, 0xFF);
-        validate_argument(0x132, 0xFFF);
-        validate_argument(0x5, 0xFF);
-        validate_argument(0x3, 0xF);
-        validate_argument(0xFFF, 0xFFF);
+        assert_eq!(validate_argument(0x54, 0xFF), Ok(0x54));
+        assert_eq!(validate_argument(0x132, 0xFFF), Ok(0x132));
+        assert_eq!(validate_argument(0x5, 0xFF), Ok(0x5));
+        assert_eq!(validate_argument(0x3, 0xF), Ok(0x3));
+        assert_eq!(validate_argument(0xFFF, 0xFFF), Ok(0xFFF));
     }
 
     #[test]
-    #[should_panic]
     pub fn validate_argument_outside_test() {
-        validate_argument(0x254, 0xFF);
+        assert_eq!(
+            validate_argument(0x254, 0xFF),
+            Err(Chip8Error::ArgumentOutOfRange {
+                value: 0x254,
+                mask: 0xFF
+            })
+        );
     }
 
     #[test]
-    #[should_panic]
     pub fn validate_argument_barely_bad_test() {
-        validate_argument(0x54, 0x53);
+        assert!(validate_argument(0x54, 0x53).is_err());
+    }
+
+    #[test]
+    pub fn crc32_test() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn sha1_test() {
+        let hex = |hash: [u8; 20]| -> String {
+            hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+        };
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        // Two blocks once padded
+        assert_eq!(
+            hex(sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+}
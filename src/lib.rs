@@ -0,0 +1,29 @@
+// Rusty Chip's emulator core.
+// The core has no knowledge of any windowing, audio or input library, frontends drive it
+// through the Chip8 API: load a ROM, run frames, press and release keys, then read back
+// the framebuffer and the sound state.
+
+pub mod chip8;
+pub mod chip8_assembler;
+pub mod chip8_capture;
+pub mod chip8_debugger;
+pub mod chip8_disassembler;
+pub mod chip8_error;
+pub mod chip8_font;
+pub mod chip8_image;
+pub mod chip8_instruction;
+pub mod chip8_keymap;
+pub mod chip8_movie;
+pub mod chip8_palette;
+pub mod chip8_quirks;
+pub mod chip8_rewind;
+pub mod chip8_rng;
+pub mod chip8_rom;
+pub mod chip8_sound;
+pub mod chip8_state;
+mod chip8_util;
+
+pub use chip8::{Chip8, StepOutcome};
+pub use chip8_debugger::{DebugCommand, Debugger};
+pub use chip8_error::Chip8Error;
+pub use chip8_quirks::{IndexIncrement, Quirks};
@@ -1,14 +1,90 @@
+use rusty_chip::chip8_rom::Rom;
+use rusty_chip::{chip8_assembler, chip8_disassembler};
 use std::env;
+use std::fs;
 use std::process;
 
-mod chip8;
+mod chip8_headless;
+#[cfg(feature = "sdl")]
 mod chip8_sdl2_gui;
-mod chip8_util;
+#[cfg(feature = "terminal")]
+mod chip8_terminal;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+// rusty-chip disasm <rom>
+fn disassemble(args: &[String]) {
+    let filename: &String = args.get(2).unwrap_or_else(|| {
+        eprintln!("Problem with arguments: disasm requires a ROM filename");
+        process::exit(1);
+    });
+    // Any program that fits in memory, the disassembler does not know the platform
+    let rom: Rom = Rom::from_path(filename, true).unwrap_or_else(|err| {
+        eprintln!("Unable to load {}: {}", filename, err);
+        process::exit(1);
+    });
+    print!("{}", chip8_disassembler::disassemble(rom.bytes()));
+}
 
-    let config = chip8_sdl2_gui::Config::new(&args).unwrap_or_else(|err| {
+// rusty-chip asm <source> <rom>
+fn assemble(args: &[String]) {
+    let (source_filename, rom_filename): (&String, &String) = match (args.get(2), args.get(3)) {
+        (Some(source_filename), Some(rom_filename)) => (source_filename, rom_filename),
+        _ => {
+            eprintln!("Problem with arguments: asm requires a source and a ROM filename");
+            process::exit(1);
+        }
+    };
+    let source: String = fs::read_to_string(source_filename).unwrap_or_else(|err| {
+        eprintln!("Unable to read {}: {}", source_filename, err);
+        process::exit(1);
+    });
+    let rom: Vec<u8> = chip8_assembler::assemble(&source).unwrap_or_else(|err| {
+        eprintln!("{} {}", source_filename, err);
+        process::exit(1);
+    });
+    fs::write(rom_filename, rom).unwrap_or_else(|err| {
+        eprintln!("Unable to write {}: {}", rom_filename, err);
+        process::exit(1);
+    });
+}
+
+// rusty-chip headless <rom> [options], exits with the status of the run
+fn run_headless(args: &[String]) {
+    let config = chip8_headless::Config::new(args).unwrap_or_else(|err| {
+        eprintln!("Problem with arguments: {}", err);
+        process::exit(1);
+    });
+    match chip8_headless::run(config) {
+        Ok(status) => process::exit(status),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+// rusty-chip term <rom> [options]
+#[cfg(feature = "terminal")]
+fn run_terminal(args: &[String]) {
+    let config = chip8_terminal::Config::new(args).unwrap_or_else(|err| {
+        eprintln!("Problem with arguments: {}", err);
+        process::exit(1);
+    });
+    if let Err(err) = chip8_terminal::run(config) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "terminal"))]
+fn run_terminal(_args: &[String]) {
+    eprintln!("This build has no terminal frontend, enable the terminal feature");
+    process::exit(1);
+}
+
+// rusty-chip <rom> [options]
+#[cfg(feature = "sdl")]
+fn run_gui(args: &[String]) {
+    let config = chip8_sdl2_gui::Config::new(args).unwrap_or_else(|err| {
         eprintln!("Problem with arguments: {}", err);
         process::exit(1);
     });
@@ -17,8 +93,29 @@ fn main() {
         Ok(_x) => {
             println!("Thank for playing!");
         }
-        Err(x) => {
-            println!("Error while running SDL2_GUI: {}", x);
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
         }
     }
 }
+
+#[cfg(not(feature = "sdl"))]
+fn run_gui(_args: &[String]) {
+    eprintln!("This build has no SDL2 frontend, run ROMs with the headless subcommand");
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        Some("disasm") => return disassemble(&args),
+        Some("asm") => return assemble(&args),
+        Some("headless") => return run_headless(&args),
+        Some("term") => return run_terminal(&args),
+        _ => {}
+    }
+
+    run_gui(&args);
+}